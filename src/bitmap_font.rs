@@ -0,0 +1,123 @@
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use gc_input::error::Result;
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+// Each glyph is five rows of three pixels, most significant bit on the left.
+fn glyph(ch: char) -> Option<[u8; 5]> {
+    let rows = match ch.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b110, 0b001, 0b010, 0b000, 0b010],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '°' => [0b010, 0b101, 0b010, 0b000, 0b000],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Whether `ch` can be drawn.
+pub fn has_glyph(ch: char) -> bool {
+    glyph(ch).is_some()
+}
+
+/// Size in pixels that `text` takes up when drawn at the given scale.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let widest = text.lines()
+        .map(|line| line.chars().count() as u32)
+        .max()
+        .unwrap_or(0);
+    let lines = text.lines().count() as u32;
+
+    let width = (widest * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale;
+    let height = (lines * (GLYPH_HEIGHT + 1)).saturating_sub(1) * scale;
+    (width, height)
+}
+
+/// Draws `text` with its top left corner at `pos`. Characters without a
+/// glyph are drawn as a filled box so they stand out.
+pub fn draw_text(canvas: &mut Canvas<Window>, text: &str, pos: (i32, i32), scale: u32, color: Color) -> Result<()> {
+    let scale = scale.max(1);
+    let mut rects = Vec::new();
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let y = pos.1 + (line_idx as u32 * (GLYPH_HEIGHT + 1) * scale) as i32;
+        for (char_idx, ch) in line.chars().enumerate() {
+            let x = pos.0 + (char_idx as u32 * (GLYPH_WIDTH + 1) * scale) as i32;
+            let rows = glyph(ch).unwrap_or([0b111; 5]);
+            for (row_idx, row) in rows.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if row & (0b100 >> col) != 0 {
+                        rects.push(Rect::new(
+                            x + (col * scale) as i32,
+                            y + (row_idx as u32 * scale) as i32,
+                            scale,
+                            scale));
+                    }
+                }
+            }
+        }
+    }
+
+    if !rects.is_empty() {
+        canvas.set_draw_color(color);
+        canvas.fill_rects(&rects)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tells readers and threads to stop what they're doing. Clones share the
+/// same state, so cancelling one cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        let (ref cancelled, ref condvar) = *self.inner;
+        *cancelled.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let (ref cancelled, _) = *self.inner;
+        *cancelled.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sleeps for `duration`, waking up early if cancelled. Returns whether
+    /// it was cancelled.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (ref cancelled, ref condvar) = *self.inner;
+        let deadline = Instant::now() + duration;
+        let mut guard = cancelled.lock().unwrap_or_else(|e| e.into_inner());
+        while !*guard {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            guard = condvar.wait_timeout(guard, deadline - now)
+                .map(|(guard, _)| guard)
+                .unwrap_or_else(|e| e.into_inner().0);
+        }
+        true
+    }
+}
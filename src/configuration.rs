@@ -1,120 +1,193 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 use serde_derive::Deserialize;
 
-use crate::error::Result;
-
-#[derive(Clone, Debug, Deserialize)]
-pub struct ImageConf {
-    pub path: String,
-    pub dst: (i32, i32),
-    pub size: Option<(u32, u32)>,
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialParity {
+    None,
+    Odd,
+    Even,
 }
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct AnalogConf {
-    pub image: ImageConf,
-    pub range: (i32, i32),
-    pub line_from: Option<(i32, i32)>,
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Console {
+    Gamecube,
+    N64,
+    Snes,
+    Nes,
+    /// Wii Classic Controller.
+    Classic,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
-pub enum TriggerDirection {
-    Up,
-    Down,
-    Left,
-    Right,
+pub enum SerialFraming {
+    /// One '0' or '1' character per bit, as sent by NintendoSpy firmware.
+    Ascii,
+    /// One byte per bit, where any non-zero byte is a 1.
+    Binary,
+    /// The raw controller report with eight bits to a byte.
+    Packed,
 }
 
 #[derive(Clone, Debug, Deserialize)]
-pub struct TriggerConf {
-    pub image: ImageConf,
-    pub direction: TriggerDirection,
+pub struct SerialConf {
+    pub path: Option<String>,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub retry_ms: Option<u64>,
+    pub baud_rate: Option<u32>,
+    pub parity: Option<SerialParity>,
+    pub timeout_ms: Option<u64>,
+    pub terminator: Option<u8>,
+    pub framing: Option<SerialFraming>,
+    pub console: Option<Console>,
+    pub capture: Option<String>,
 }
 
+/// The serial input can be given as just a port name or with full settings.
 #[derive(Clone, Debug, Deserialize)]
-pub struct ThemeConfiguration {
-    pub size: (u32, u32),
-    pub background: ImageConf,
-    pub a: Option<ImageConf>,
-    pub b: Option<ImageConf>,
-    pub x: Option<ImageConf>,
-    pub y: Option<ImageConf>,
-    pub up: Option<ImageConf>,
-    pub down: Option<ImageConf>,
-    pub left: Option<ImageConf>,
-    pub right: Option<ImageConf>,
-    pub start: Option<ImageConf>,
-    pub analog: Option<AnalogConf>,
-    pub c: Option<AnalogConf>,
-    pub l_analog: Option<TriggerConf>,
-    pub r_analog: Option<TriggerConf>,
-    pub l_digital: Option<ImageConf>,
-    pub r_digital: Option<ImageConf>,
-    pub z: Option<ImageConf>,
-}
-
-impl ThemeConfiguration {
-    pub fn from_read<R>(reader: R) -> Result<ThemeConfiguration>
-        where R: Read,
-    {
-        Ok(serde_yaml::from_reader(reader)?)
-    }
+#[serde(untagged)]
+pub enum SerialInput {
+    Path(String),
+    Settings(SerialConf),
+}
 
-    pub fn from_path<P>(path: P) -> Result<ThemeConfiguration>
-        where P: AsRef<Path>,
-    {
-        let file = File::open(path)?;
-        Self::from_read(file)
+impl SerialInput {
+    pub fn into_conf(self) -> SerialConf {
+        match self {
+            SerialInput::Path(path) => SerialConf {
+                path: Some(path),
+                vid: None,
+                pid: None,
+                retry_ms: None,
+                baud_rate: None,
+                parity: None,
+                timeout_ms: None,
+                terminator: None,
+                framing: None,
+                console: None,
+                capture: None,
+            },
+            SerialInput::Settings(conf) => conf,
+        }
     }
 }
 
+/// Keys for each direction of a stick, by SDL key name.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StickKeys {
+    pub up: Option<String>,
+    pub down: Option<String>,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Which keys drive which parts of the controller when using the keyboard.
+/// Keys are SDL key names, such as `X`, `Return`, or `Left Shift`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct KeyboardConf {
+    pub buttons: Option<BTreeMap<String, String>>,
+    pub analog: Option<StickKeys>,
+    pub c: Option<StickKeys>,
+    pub l: Option<String>,
+    pub r: Option<String>,
+    pub tilt: Option<String>,
+    pub tilt_amount: Option<f32>,
+}
+
+/// Which SDL axes drive each axis of the controller, by the names used in
+/// SDL controller mappings.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SdlAxes {
+    pub analog_x: Option<String>,
+    pub analog_y: Option<String>,
+    pub c_x: Option<String>,
+    pub c_y: Option<String>,
+    pub l_analog: Option<String>,
+    pub r_analog: Option<String>,
+}
+
+/// A joystick or gamepad read through SDL. Picks the pad with `guid` if it's
+/// given, or else the one at `index`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SdlConf {
+    pub index: Option<usize>,
+    pub guid: Option<String>,
+    pub mapping: Option<String>,
+    pub buttons: Option<BTreeMap<String, String>>,
+    pub axes: Option<SdlAxes>,
+    pub click_threshold: Option<f32>,
+}
+
+/// Adjustments to a single axis. Values are in raw units from 0 to 255.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AxisConf {
+    pub offset: Option<i32>,
+    pub scale: Option<f32>,
+    pub deadzone: Option<u8>,
+    pub invert: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TransformConf {
+    pub analog_x: Option<AxisConf>,
+    pub analog_y: Option<AxisConf>,
+    pub c_x: Option<AxisConf>,
+    pub c_y: Option<AxisConf>,
+    pub l_analog: Option<AxisConf>,
+    pub r_analog: Option<AxisConf>,
+    pub remap: Option<BTreeMap<String, String>>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum InputSource {
     Dtm(String),
     Sa2(Option<String>),
-    Serial(String),
+    Serial(SerialInput),
+    Replay(String),
+    Keyboard(Option<KeyboardConf>),
+    Sdl(Option<SdlConf>),
+    #[serde(rename = "dolphin_pipe")]
+    DolphinPipe(String),
 }
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct Configuration {
-    pub theme: ThemeConfiguration,
-    pub theme_path: PathBuf,
-    pub input: InputSource,
-}
-
-impl Configuration {
-    pub fn from_read<R>(reader: R) -> Result<Configuration>
-        where R: Read,
-    {
-        let conf_file: ConfigurationFile = serde_yaml::from_reader(reader)?;
-        let theme = ThemeConfiguration::from_path(&conf_file.theme_path)?;
-        let theme_path = conf_file.theme_path
-            .parent()
-            .unwrap_or(Path::new("/"))
-            .to_owned();
-
-        Ok(Configuration {
-            theme: theme,
-            theme_path: theme_path,
-            input: conf_file.input,
-        })
-    }
-
-    pub fn from_path<P>(path: P) -> Result<Configuration>
-        where P: AsRef<Path>,
-    {
-        let file = File::open(path)?;
-        Self::from_read(file)
+impl InputSource {
+    /// The name of the input method in `conf.yaml`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            InputSource::Dtm(_) => "dtm",
+            InputSource::Sa2(_) => "sa2",
+            InputSource::Serial(_) => "serial",
+            InputSource::Replay(_) => "replay",
+            InputSource::Keyboard(_) => "keyboard",
+            InputSource::Sdl(_) => "sdl",
+            InputSource::DolphinPipe(_) => "dolphin_pipe",
+        }
     }
 }
 
+/// Somewhere to send the controller state.
 #[derive(Clone, Debug, Deserialize)]
-pub struct ConfigurationFile {
-    theme_path: PathBuf,
-    input: InputSource,
+#[serde(rename_all = "snake_case")]
+pub enum OutputConf {
+    /// A CSV file of every change to the input.
+    Recorder(PathBuf),
+    /// UDP packets to an address.
+    Network(String),
+    /// A JSON file of the session's stats, saved on exit.
+    Stats(PathBuf),
+    /// One of Dolphin's input pipes.
+    DolphinPipe(PathBuf),
+    /// A virtual gamepad, on Linux.
+    Uinput(Option<UinputConf>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct UinputConf {
+    pub name: Option<String>,
 }
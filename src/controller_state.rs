@@ -1,4 +1,101 @@
-#[derive(Clone, Copy, Debug)]
+use crate::metadata::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+    Up,
+    Down,
+    Left,
+    Right,
+    Start,
+    LDigital,
+    RDigital,
+    Z,
+    Select,
+    Home,
+    Zl,
+    Zr,
+    CUp,
+    CDown,
+    CLeft,
+    CRight,
+}
+
+impl Button {
+    pub const ALL: [Button; 20] = [
+        Button::A,
+        Button::B,
+        Button::X,
+        Button::Y,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+        Button::Start,
+        Button::LDigital,
+        Button::RDigital,
+        Button::Z,
+        Button::Select,
+        Button::Home,
+        Button::Zl,
+        Button::Zr,
+        Button::CUp,
+        Button::CDown,
+        Button::CLeft,
+        Button::CRight,
+    ];
+
+    /// Buttons on a GameCube controller.
+    pub const GAMECUBE: [Button; 12] = [
+        Button::A,
+        Button::B,
+        Button::X,
+        Button::Y,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+        Button::Start,
+        Button::LDigital,
+        Button::RDigital,
+        Button::Z,
+    ];
+
+    /// The name used for this button in configuration and theme files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::A => "a",
+            Button::B => "b",
+            Button::X => "x",
+            Button::Y => "y",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::Start => "start",
+            Button::LDigital => "l_digital",
+            Button::RDigital => "r_digital",
+            Button::Z => "z",
+            Button::Select => "select",
+            Button::Home => "home",
+            Button::Zl => "zl",
+            Button::Zr => "zr",
+            Button::CUp => "c_up",
+            Button::CDown => "c_down",
+            Button::CLeft => "c_left",
+            Button::CRight => "c_right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        Self::ALL.iter().cloned().find(|button| button.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ControllerState {
     pub a: bool,
     pub b: bool,
@@ -16,6 +113,85 @@ pub struct ControllerState {
     pub l_digital: bool,
     pub r_digital: bool,
     pub z: bool,
+    pub select: bool,
+    pub home: bool,
+    pub zl: bool,
+    pub zr: bool,
+    pub c_up: bool,
+    pub c_down: bool,
+    pub c_left: bool,
+    pub c_right: bool,
+}
+
+impl ControllerState {
+    pub fn button(&self, button: Button) -> bool {
+        match button {
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::X => self.x,
+            Button::Y => self.y,
+            Button::Up => self.up,
+            Button::Down => self.down,
+            Button::Left => self.left,
+            Button::Right => self.right,
+            Button::Start => self.start,
+            Button::LDigital => self.l_digital,
+            Button::RDigital => self.r_digital,
+            Button::Z => self.z,
+            Button::Select => self.select,
+            Button::Home => self.home,
+            Button::Zl => self.zl,
+            Button::Zr => self.zr,
+            Button::CUp => self.c_up,
+            Button::CDown => self.c_down,
+            Button::CLeft => self.c_left,
+            Button::CRight => self.c_right,
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        match button {
+            Button::A => self.a = pressed,
+            Button::B => self.b = pressed,
+            Button::X => self.x = pressed,
+            Button::Y => self.y = pressed,
+            Button::Up => self.up = pressed,
+            Button::Down => self.down = pressed,
+            Button::Left => self.left = pressed,
+            Button::Right => self.right = pressed,
+            Button::Start => self.start = pressed,
+            Button::LDigital => self.l_digital = pressed,
+            Button::RDigital => self.r_digital = pressed,
+            Button::Z => self.z = pressed,
+            Button::Select => self.select = pressed,
+            Button::Home => self.home = pressed,
+            Button::Zl => self.zl = pressed,
+            Button::Zr => self.zr = pressed,
+            Button::CUp => self.c_up = pressed,
+            Button::CDown => self.c_down = pressed,
+            Button::CLeft => self.c_left = pressed,
+            Button::CRight => self.c_right = pressed,
+        }
+    }
+
+    /// Looks up a value by the name text templates use for it, such as `a`
+    /// or `analog.x`. Buttons are 1 when pressed and 0 otherwise.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        if let Some(button) = Button::from_name(name) {
+            return Some(self.button(button).into());
+        }
+
+        let value = match name {
+            "analog.x" => self.analog.0,
+            "analog.y" => self.analog.1,
+            "c.x" => self.c.0,
+            "c.y" => self.c.1,
+            "l_analog" => self.l_analog,
+            "r_analog" => self.r_analog,
+            _ => return None,
+        };
+        Some(value.into())
+    }
 }
 
 impl Default for ControllerState {
@@ -37,6 +213,62 @@ impl Default for ControllerState {
             l_digital: false,
             r_digital: false,
             z: false,
+            select: false,
+            home: false,
+            zl: false,
+            zr: false,
+            c_up: false,
+            c_down: false,
+            c_left: false,
+            c_right: false,
+        }
+    }
+}
+
+/// A stick position in game terms: each axis goes from -1.0 to 1.0 with up
+/// and right being positive.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StickPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl StickPosition {
+    pub fn from_raw(raw: (u8, u8)) -> StickPosition {
+        StickPosition {
+            x: normalize_axis(raw.0),
+            y: normalize_axis(raw.1),
+        }
+    }
+
+    /// Same as `from_raw`, but anything within `deadzone` of the center is
+    /// reported as neutral, the way games read the stick.
+    pub fn from_raw_with_deadzone(raw: (u8, u8), deadzone: f32) -> StickPosition {
+        let position = Self::from_raw(raw);
+        if position.magnitude() < deadzone {
+            StickPosition::default()
+        }
+        else {
+            position
+        }
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        self.x.hypot(self.y).min(1.0)
+    }
+
+    /// Angle in degrees counter-clockwise from the right, in [0, 360).
+    pub fn angle(&self) -> f32 {
+        let angle = self.y.atan2(self.x).to_degrees();
+        if angle < 0.0 {
+            angle + 360.0
+        }
+        else {
+            angle
         }
     }
-}
\ No newline at end of file
+}
+
+fn normalize_axis(value: u8) -> f32 {
+    ((value as f32 - 128.0) / 127.0).max(-1.0).min(1.0)
+}
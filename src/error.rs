@@ -1,9 +1,11 @@
 use std::error;
 use std::fmt;
 use std::io::Error as IoError;
+use std::path::PathBuf;
 use std::result;
 
 use dtm2txt::error::Dtm2txtError as DtmError;
+use serde_json::Error as JsonError;
 use serde_yaml::Error as YamlError;
 use serialport::Error as SerialError;
 
@@ -11,10 +13,24 @@ use serialport::Error as SerialError;
 pub enum Error {
     IoError(IoError),
     YamlError(YamlError),
+    JsonError(JsonError),
     Sdl2Error(Box<dyn error::Error>),
     DtmError(DtmError),
     SerialError(SerialError),
     ProcessError(&'static str),
+    FormatError(String),
+    /// The log level or log settings were wrong, on the command line or in
+    /// `conf.yaml`.
+    LogError(String),
+    /// Something this platform can't do, like making virtual controllers
+    /// anywhere but Linux.
+    UnsupportedPlatform(&'static str),
+    /// Something was wrong with a configuration or theme file.
+    ConfigError(PathBuf, Box<Error>),
+    /// An input method failed, named by its `conf.yaml` name.
+    ReaderError(&'static str, Box<Error>),
+    /// A reader was told to stop through its `CancelToken`.
+    Cancelled,
 }
 
 impl fmt::Display for Error {
@@ -22,10 +38,17 @@ impl fmt::Display for Error {
         match *self {
             Error::IoError(ref err) => write!(f, "IO Error: {}", err),
             Error::YamlError(ref err) => write!(f, "Yaml Error: {}", err),
+            Error::JsonError(ref err) => write!(f, "Json Error: {}", err),
             Error::Sdl2Error(ref err) => write!(f, "Sdl2 Error: {}", err),
             Error::DtmError(ref err) => write!(f, "Dtm Error: {}", err),
             Error::SerialError(ref err) => write!(f, "Serial Error: {}", err),
             Error::ProcessError(ref err) => write!(f, "Process Error: {}", err),
+            Error::FormatError(ref err) => write!(f, "Format Error: {}", err),
+            Error::LogError(ref err) => write!(f, "Log Error: {}", err),
+            Error::UnsupportedPlatform(ref err) => write!(f, "Unsupported on this platform: {}", err),
+            Error::ConfigError(ref path, ref err) => write!(f, "Error in \"{}\": {}", path.display(), err),
+            Error::ReaderError(source, ref err) => write!(f, "Error reading {} input: {}", source, err),
+            Error::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
@@ -35,10 +58,17 @@ impl error::Error for Error {
         match *self {
             Error::IoError(ref err) => err.description(),
             Error::YamlError(ref err) => err.description(),
+            Error::JsonError(ref err) => err.description(),
             Error::Sdl2Error(ref err) => err.description(),
             Error::DtmError(ref err) => err.description(),
             Error::SerialError(ref err) => err.description(),
             Error::ProcessError(ref err) => err,
+            Error::FormatError(ref err) => err,
+            Error::LogError(ref err) => err,
+            Error::UnsupportedPlatform(ref err) => err,
+            Error::ConfigError(_, ref err) => err.description(),
+            Error::ReaderError(_, ref err) => err.description(),
+            Error::Cancelled => "cancelled",
         }
     }
 
@@ -46,11 +76,18 @@ impl error::Error for Error {
         match *self {
             Error::IoError(ref err) => Some(err),
             Error::YamlError(ref err) => Some(err),
+            Error::JsonError(ref err) => Some(err),
             // TODO: Figure out how to toss out err.
             Error::Sdl2Error(ref _err) => None,
             Error::DtmError(ref err) => Some(err),
             Error::SerialError(ref err) => Some(err),
             Error::ProcessError(_) => None,
+            Error::FormatError(_) => None,
+            Error::LogError(_) => None,
+            Error::UnsupportedPlatform(_) => None,
+            Error::ConfigError(_, ref err) => Some(&**err),
+            Error::ReaderError(_, ref err) => Some(&**err),
+            Error::Cancelled => None,
         }
     }
 }
@@ -67,6 +104,12 @@ impl From<YamlError> for Error {
     }
 }
 
+impl From<JsonError> for Error {
+    fn from(err: JsonError) -> Error {
+        Error::JsonError(err)
+    }
+}
+
 impl From<String> for Error {
     fn from(err: String) -> Error {
         Error::Sdl2Error(err.into())
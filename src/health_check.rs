@@ -0,0 +1,277 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::controller_state::{Button, ControllerState, StickPosition};
+use crate::error::Result;
+use crate::input_reader::InputReader;
+
+const COUNTDOWN_SECS: u64 = 3;
+const REST_TIME: Duration = Duration::from_secs(3);
+const ROTATE_TIME: Duration = Duration::from_secs(10);
+const BUTTON_TIME: Duration = Duration::from_secs(20);
+const TRIGGER_TIME: Duration = Duration::from_secs(8);
+// Only positions this far out count towards a notch's range.
+const NOTCH_MIN_MAGNITUDE: f32 = 0.5;
+// A notch that reaches less than this much of the best notch gets flagged.
+const LOW_NOTCH: f64 = 0.9;
+const NOTCH_NAMES: [&'static str; 8] = [
+    "right", "up-right", "up", "up-left", "left", "down-left", "down", "down-right",
+];
+
+/// Running mean, spread, and range of a value.
+#[derive(Clone, Copy, Debug, Default)]
+struct Samples {
+    count: u64,
+    sum: f64,
+    sum_sq: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Samples {
+    fn record(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.sum_sq += value * value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        }
+        else {
+            self.sum / self.count as f64
+        }
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_sq / self.count as f64 - mean * mean).max(0.0).sqrt()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct StickRest {
+    x: Samples,
+    y: Samples,
+}
+
+impl StickRest {
+    fn record(&mut self, raw: (u8, u8)) {
+        self.x.record(raw.0 as f64);
+        self.y.record(raw.1 as f64);
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+        writeln!(f, "{}:", name)?;
+        writeln!(f, "    center offset: ({:+.1}, {:+.1})", self.x.mean() - 128.0, self.y.mean() - 128.0)?;
+        writeln!(f, "    noise: ({:.2}, {:.2}) std dev, ({}, {}) range",
+            self.x.std_dev(),
+            self.y.std_dev(),
+            self.x.max - self.x.min,
+            self.y.max - self.y.min)
+    }
+}
+
+/// Furthest the stick got from the center near each notch, in raw units.
+#[derive(Clone, Copy, Debug, Default)]
+struct StickRange {
+    notches: [f64; 8],
+}
+
+impl StickRange {
+    fn record(&mut self, raw: (u8, u8)) {
+        let position = StickPosition::from_raw(raw);
+        if position.magnitude() < NOTCH_MIN_MAGNITUDE {
+            return;
+        }
+        let notch = ((position.angle() + 22.5) / 45.0) as usize % 8;
+        let distance = (raw.0 as f64 - 128.0).hypot(raw.1 as f64 - 128.0);
+        self.notches[notch] = self.notches[notch].max(distance);
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+        let best = self.notches.iter().cloned().fold(0.0, f64::max);
+        writeln!(f, "{}:", name)?;
+        for (notch_name, &distance) in NOTCH_NAMES.iter().zip(self.notches.iter()) {
+            let flag = if distance < best * LOW_NOTCH { "  (low)" } else { "" };
+            writeln!(f, "    {:<10} {:5.1}{}", notch_name, distance, flag)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct TriggerCheck {
+    rest: Samples,
+    max: u8,
+    click_pressures: Vec<u8>,
+    clicked: bool,
+}
+
+impl TriggerCheck {
+    fn record(&mut self, analog: u8, digital: bool) {
+        self.max = self.max.max(analog);
+        if digital && !self.clicked {
+            self.click_pressures.push(analog);
+        }
+        self.clicked = digital;
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+        writeln!(f, "{}:", name)?;
+        writeln!(f, "    rest: {:.1}", self.rest.mean())?;
+        writeln!(f, "    max: {}", self.max)?;
+        if self.click_pressures.is_empty() {
+            writeln!(f, "    digital click: never clicked")
+        }
+        else {
+            let lowest = self.click_pressures.iter().min().cloned().unwrap_or(0);
+            let highest = self.click_pressures.iter().max().cloned().unwrap_or(0);
+            writeln!(f, "    digital click: at {} to {} over {} clicks", lowest, highest, self.click_pressures.len())
+        }
+    }
+}
+
+/// What the health check found. Displaying it gives the full report.
+#[derive(Clone, Debug)]
+pub struct HealthReport {
+    /// Buttons that were never pressed.
+    pub dead: Vec<Button>,
+    /// Buttons that were held while the controller was left alone.
+    pub stuck: Vec<Button>,
+    analog_rest: StickRest,
+    c_rest: StickRest,
+    analog_range: StickRange,
+    c_range: StickRange,
+    l_trigger: TriggerCheck,
+    r_trigger: TriggerCheck,
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Dead buttons: {}", button_list(&self.dead))?;
+        writeln!(f, "Stuck buttons: {}", button_list(&self.stuck))?;
+        writeln!(f)?;
+        self.analog_rest.write(f, "Analog stick at rest")?;
+        self.c_rest.write(f, "C stick at rest")?;
+        writeln!(f)?;
+        self.analog_range.write(f, "Analog stick range per notch")?;
+        self.c_range.write(f, "C stick range per notch")?;
+        writeln!(f)?;
+        self.l_trigger.write(f, "L trigger")?;
+        self.r_trigger.write(f, "R trigger")
+    }
+}
+
+/// Walks the player through using every part of the controller and reports
+/// anything that looks wrong. Each line of instructions is handed to `show`
+/// as the check goes.
+pub fn run<F>(reader: &mut dyn InputReader, mut show: F) -> Result<HealthReport>
+    where F: FnMut(&str),
+{
+    let buttons = reader.buttons();
+
+    let mut analog_rest = StickRest::default();
+    let mut c_rest = StickRest::default();
+    let mut l_trigger = TriggerCheck::default();
+    let mut r_trigger = TriggerCheck::default();
+    let mut stuck = Vec::new();
+    run_step(reader, &mut show, "Put the controller down and don't touch it.", REST_TIME, |state| {
+        analog_rest.record(state.analog);
+        c_rest.record(state.c);
+        l_trigger.rest.record(state.l_analog as f64);
+        r_trigger.rest.record(state.r_analog as f64);
+        for &button in buttons.iter() {
+            if state.button(button) && !stuck.contains(&button) {
+                stuck.push(button);
+            }
+        }
+        false
+    })?;
+
+    let mut analog_range = StickRange::default();
+    let mut c_range = StickRange::default();
+    run_step(reader, &mut show, "Slowly rotate both sticks around the edge of their gates a few times.", ROTATE_TIME, |state| {
+        analog_range.record(state.analog);
+        c_range.record(state.c);
+        false
+    })?;
+
+    let mut pressed = Vec::new();
+    run_step(reader, &mut show, "Press each button once.", BUTTON_TIME, |state| {
+        for &button in buttons.iter() {
+            if state.button(button) && !pressed.contains(&button) {
+                pressed.push(button);
+            }
+        }
+        // Move on as soon as everything has been pressed.
+        pressed.len() == buttons.len()
+    })?;
+
+    run_step(reader, &mut show, "Slowly squeeze each trigger all the way in and let go, a few times.", TRIGGER_TIME, |state| {
+        l_trigger.record(state.l_analog, state.l_digital);
+        r_trigger.record(state.r_analog, state.r_digital);
+        false
+    })?;
+
+    Ok(HealthReport {
+        dead: buttons.iter().cloned().filter(|b| !pressed.contains(b)).collect(),
+        stuck: stuck,
+        analog_rest: analog_rest,
+        c_rest: c_rest,
+        analog_range: analog_range,
+        c_range: c_range,
+        l_trigger: l_trigger,
+        r_trigger: r_trigger,
+    })
+}
+
+/// Shows `prompt`, counts down, then hands every input to `record` until
+/// `duration` is up or `record` returns true.
+fn run_step<F>(reader: &mut dyn InputReader, show: &mut dyn FnMut(&str), prompt: &str, duration: Duration, record: F) -> Result<()>
+    where F: FnMut(&ControllerState) -> bool,
+{
+    show("");
+    show(prompt);
+    // Keep reading during the countdown so the reader doesn't fall behind.
+    for remaining in (1..COUNTDOWN_SECS + 1).rev() {
+        show(&format!("{}...", remaining));
+        read_for(reader, Duration::from_secs(1), |_| false)?;
+    }
+    show(&format!("Go! ({} seconds)", duration.as_secs()));
+    read_for(reader, duration, record)?;
+    show("Done.");
+    Ok(())
+}
+
+fn read_for<F>(reader: &mut dyn InputReader, duration: Duration, mut record: F) -> Result<()>
+    where F: FnMut(&ControllerState) -> bool,
+{
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let state = reader.read_next_input()?;
+        if reader.is_connected() && record(&state) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn button_list(buttons: &[Button]) -> String {
+    if buttons.is_empty() {
+        "none".to_string()
+    }
+    else {
+        buttons.iter().map(|b| b.name()).collect::<Vec<_>>().join(", ")
+    }
+}
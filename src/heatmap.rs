@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+use std::fmt;
+
+use gc_input::controller_state::{ControllerState, StickPosition};
+use gc_input::metadata::Metadata;
+
+/// Width and height of a heatmap, one cell per raw stick value.
+pub const HEATMAP_SIZE: u32 = 256;
+// Size in raw units of the cells used to work out gate coverage.
+const COVERAGE_CELL: u32 = 8;
+const COVERAGE_CELLS: u32 = HEATMAP_SIZE / COVERAGE_CELL;
+// Distance in raw units from the center to a notch of a typical gate.
+const GATE_RADIUS: f32 = 100.0;
+// The stick counts as resting when it's this close to the center.
+const REST_RADIUS: f32 = 0.2;
+
+/// Counts how often a stick has been at each position.
+#[derive(Clone, Debug)]
+pub struct Heatmap {
+    counts: Vec<u32>,
+    max_count: u32,
+    // Which coverage cells the stick has been in, and how many of the ones
+    // inside the gate that is. Kept up to date as the stick moves so the
+    // report doesn't have to look at every cell.
+    visited: Vec<bool>,
+    visited_in_gate: u32,
+    gate_cells: u32,
+    min: (u8, u8),
+    max: (u8, u8),
+    rest_samples: u64,
+    rest_sum: (f64, f64),
+    rest_sum_sq: (f64, f64),
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap {
+            counts: vec![0; (HEATMAP_SIZE * HEATMAP_SIZE) as usize],
+            max_count: 0,
+            visited: vec![false; (COVERAGE_CELLS * COVERAGE_CELLS) as usize],
+            visited_in_gate: 0,
+            gate_cells: gate_cells(),
+            min: (255, 255),
+            max: (0, 0),
+            rest_samples: 0,
+            rest_sum: (0.0, 0.0),
+            rest_sum_sq: (0.0, 0.0),
+        }
+    }
+
+    pub fn record(&mut self, raw: (u8, u8)) {
+        let idx = raw.1 as usize * HEATMAP_SIZE as usize + raw.0 as usize;
+        self.counts[idx] = self.counts[idx].saturating_add(1);
+        self.max_count = self.max_count.max(self.counts[idx]);
+        let (cell_x, cell_y) = (raw.0 as u32 / COVERAGE_CELL, raw.1 as u32 / COVERAGE_CELL);
+        let cell = (cell_y * COVERAGE_CELLS + cell_x) as usize;
+        if !self.visited[cell] {
+            self.visited[cell] = true;
+            if cell_in_gate(cell_x, cell_y) {
+                self.visited_in_gate += 1;
+            }
+        }
+
+        self.min = (self.min.0.min(raw.0), self.min.1.min(raw.1));
+        self.max = (self.max.0.max(raw.0), self.max.1.max(raw.1));
+
+        if StickPosition::from_raw(raw).magnitude() < REST_RADIUS {
+            let (x, y) = (raw.0 as f64, raw.1 as f64);
+            self.rest_samples += 1;
+            self.rest_sum = (self.rest_sum.0 + x, self.rest_sum.1 + y);
+            self.rest_sum_sq = (self.rest_sum_sq.0 + x * x, self.rest_sum_sq.1 + y * y);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_count == 0
+    }
+
+    /// Pixels of the heatmap as native-endian `0xRRGGBBAA` words, with `y`
+    /// pointing up like the stick does. Each position is drawn in `color`,
+    /// more opaque the more often it was visited.
+    pub fn pixels(&self, color: (u8, u8, u8)) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.counts.len() * 4);
+        let max = (self.max_count as f32 + 1.0).ln();
+        for row in (0..HEATMAP_SIZE).rev() {
+            for col in 0..HEATMAP_SIZE {
+                let count = self.counts[(row * HEATMAP_SIZE + col) as usize];
+                // Log scale so places the stick only passes through still show.
+                let alpha = if count == 0 {
+                    0
+                }
+                else {
+                    (64.0 + 191.0 * (count as f32 + 1.0).ln() / max) as u32
+                };
+                let pixel = (color.0 as u32) << 24 | (color.1 as u32) << 16 | (color.2 as u32) << 8 | alpha;
+                pixels.extend_from_slice(&pixel.to_ne_bytes());
+            }
+        }
+        pixels
+    }
+
+    /// Percentage of the gate that the stick has been in.
+    fn coverage(&self) -> f64 {
+        if self.gate_cells == 0 {
+            0.0
+        }
+        else {
+            self.visited_in_gate as f64 * 100.0 / self.gate_cells as f64
+        }
+    }
+
+    pub fn report(&self) -> HeatmapReport {
+        let (rest_center, rest_spread) = if self.rest_samples == 0 {
+            ((128.0, 128.0), (0.0, 0.0))
+        }
+        else {
+            let n = self.rest_samples as f64;
+            let mean = (self.rest_sum.0 / n, self.rest_sum.1 / n);
+            let variance = (
+                (self.rest_sum_sq.0 / n - mean.0 * mean.0).max(0.0),
+                (self.rest_sum_sq.1 / n - mean.1 * mean.1).max(0.0));
+            (mean, (variance.0.sqrt(), variance.1.sqrt()))
+        };
+
+        HeatmapReport {
+            min: if self.is_empty() { (128, 128) } else { self.min },
+            max: if self.is_empty() { (128, 128) } else { self.max },
+            rest_center: rest_center,
+            rest_spread: rest_spread,
+            coverage: self.coverage(),
+        }
+    }
+}
+
+// Whether the center of a coverage cell is inside the gate.
+fn cell_in_gate(cell_x: u32, cell_y: u32) -> bool {
+    let center_x = (cell_x * COVERAGE_CELL + COVERAGE_CELL / 2) as f32 - 128.0;
+    let center_y = (cell_y * COVERAGE_CELL + COVERAGE_CELL / 2) as f32 - 128.0;
+    in_octagon(center_x, center_y, GATE_RADIUS)
+}
+
+fn gate_cells() -> u32 {
+    let mut count = 0;
+    for cell_y in 0..COVERAGE_CELLS {
+        for cell_x in 0..COVERAGE_CELLS {
+            if cell_in_gate(cell_x, cell_y) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+// Whether (x, y) is inside a regular octagon with its corners `radius` away
+// from the center, at the usual notch angles.
+fn in_octagon(x: f32, y: f32, radius: f32) -> bool {
+    let apothem = radius * (PI / 8.0).cos();
+    (0..8).all(|side| {
+        let angle = PI / 8.0 + side as f32 * PI / 4.0;
+        x * angle.cos() + y * angle.sin() <= apothem
+    })
+}
+
+/// Summary of where a stick has been.
+#[derive(Clone, Debug)]
+pub struct HeatmapReport {
+    pub min: (u8, u8),
+    pub max: (u8, u8),
+    pub rest_center: (f64, f64),
+    pub rest_spread: (f64, f64),
+    pub coverage: f64,
+}
+
+impl HeatmapReport {
+    fn add_metadata(&self, prefix: &str, metadata: &mut Metadata) {
+        metadata.insert(format!("{}.min_x", prefix), self.min.0.into());
+        metadata.insert(format!("{}.min_y", prefix), self.min.1.into());
+        metadata.insert(format!("{}.max_x", prefix), self.max.0.into());
+        metadata.insert(format!("{}.max_y", prefix), self.max.1.into());
+        metadata.insert(format!("{}.rest_x", prefix), self.rest_center.0.into());
+        metadata.insert(format!("{}.rest_y", prefix), self.rest_center.1.into());
+        metadata.insert(format!("{}.rest_spread_x", prefix), self.rest_spread.0.into());
+        metadata.insert(format!("{}.rest_spread_y", prefix), self.rest_spread.1.into());
+        metadata.insert(format!("{}.coverage", prefix), self.coverage.into());
+    }
+}
+
+impl fmt::Display for HeatmapReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    x range: {} to {}", self.min.0, self.max.0)?;
+        writeln!(f, "    y range: {} to {}", self.min.1, self.max.1)?;
+        writeln!(f, "    rest center: ({:.1}, {:.1})", self.rest_center.0, self.rest_center.1)?;
+        writeln!(f, "    rest spread: ({:.2}, {:.2})", self.rest_spread.0, self.rest_spread.1)?;
+        write!(f, "    gate coverage: {:.1}%", self.coverage)
+    }
+}
+
+/// Heatmaps for both sticks.
+#[derive(Clone, Debug)]
+pub struct Heatmaps {
+    pub analog: Heatmap,
+    pub c: Heatmap,
+}
+
+impl Heatmaps {
+    pub fn new() -> Heatmaps {
+        Heatmaps {
+            analog: Heatmap::new(),
+            c: Heatmap::new(),
+        }
+    }
+
+    pub fn record(&mut self, state: &ControllerState) {
+        self.analog.record(state.analog);
+        self.c.record(state.c);
+    }
+
+    pub fn add_metadata(&self, metadata: &mut Metadata) {
+        self.analog.report().add_metadata("heatmap.analog", metadata);
+        self.c.report().add_metadata("heatmap.c", metadata);
+    }
+}
@@ -1,11 +1,116 @@
 pub mod dtm_reader;
 pub mod serial_reader;
+pub mod serial_capture;
+pub mod replay_reader;
+pub mod keyboard_reader;
+pub mod sdl_reader;
+pub mod dolphin_pipe_reader;
 #[cfg(windows)]
 pub mod sa2_reader;
 
-use crate::controller_state::ControllerState;
+use std::sync::{Arc, Mutex};
+
+use crate::cancel::CancelToken;
+use crate::configuration::InputSource;
+use crate::controller_state::{Button, ControllerState};
 use crate::error::Result;
+#[cfg(not(windows))]
+use crate::error::Error;
+use crate::metadata::Metadata;
+use self::dtm_reader::DtmReader;
+use self::serial_reader::SerialReader;
+use self::replay_reader::ReplayReader;
+use self::keyboard_reader::{KeyboardInput, KeyboardReader};
+use self::sdl_reader::{SdlPads, SdlReader};
+use self::dolphin_pipe_reader::DolphinPipeReader;
+#[cfg(windows)]
+use self::sa2_reader::Sa2Reader;
 
 pub trait InputReader {
     fn read_next_input(&mut self) -> Result<ControllerState>;
+
+    /// Extra values about the reader itself, such as the current frame of a
+    /// recording, for use in text elements.
+    fn metadata(&self) -> Metadata {
+        Metadata::new()
+    }
+
+    /// Whether the reader currently has something to read from. Readers that
+    /// lose their device keep returning neutral input until it comes back.
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Lets `cancel` interrupt this reader. Once it's cancelled, anything the
+    /// reader is waiting on stops early and `read_next_input` returns
+    /// `Error::Cancelled`.
+    fn set_cancel_token(&mut self, _cancel: CancelToken) {}
+
+    /// Buttons the controller being read actually has.
+    fn buttons(&self) -> &'static [Button] {
+        &Button::GAMECUBE
+    }
+}
+
+/// Everything besides their configuration that readers can need from
+/// whatever is opening them.
+#[derive(Clone, Debug, Default)]
+pub struct ReaderContext {
+    /// Interrupts readers that are waiting on something.
+    pub cancel: CancelToken,
+    /// Keys and mouse drags from the viewer window, for the keyboard input
+    /// method.
+    pub keyboard: Arc<Mutex<KeyboardInput>>,
+    /// Pads that SDL knows about, for the sdl input method.
+    pub pads: Arc<Mutex<SdlPads>>,
+}
+
+impl ReaderContext {
+    pub fn new() -> ReaderContext {
+        ReaderContext::default()
+    }
+}
+
+/// Opens whatever input method `source` describes.
+pub fn from_source(source: InputSource, context: &ReaderContext) -> Result<Box<dyn InputReader>> {
+    let mut reader: Box<dyn InputReader> = match source {
+        InputSource::Dtm(path) => {
+            let dtm_reader = DtmReader::from_path(&path)?;
+            Box::new(dtm_reader)
+        }
+        InputSource::Sa2(exe_name) => open_sa2(exe_name)?,
+        InputSource::Serial(serial) => {
+            let serial_reader = SerialReader::from_conf(&serial.into_conf())?;
+            Box::new(serial_reader)
+        }
+        InputSource::Replay(path) => {
+            let replay_reader = ReplayReader::from_path(&path)?;
+            Box::new(replay_reader)
+        }
+        InputSource::Keyboard(keyboard) => {
+            let keyboard_reader = KeyboardReader::from_conf(&keyboard.unwrap_or_default(), context.keyboard.clone())?;
+            Box::new(keyboard_reader)
+        }
+        InputSource::Sdl(sdl) => {
+            let sdl_reader = SdlReader::from_conf(&sdl.unwrap_or_default(), context.pads.clone())?;
+            Box::new(sdl_reader)
+        }
+        InputSource::DolphinPipe(path) => {
+            let dolphin_pipe_reader = DolphinPipeReader::from_path(&path)?;
+            Box::new(dolphin_pipe_reader)
+        }
+    };
+    reader.set_cancel_token(context.cancel.clone());
+    Ok(reader)
+}
+
+#[cfg(windows)]
+fn open_sa2(exe_name: Option<String>) -> Result<Box<dyn InputReader>> {
+    let sa2_reader = Sa2Reader::new(exe_name)?;
+    Ok(Box::new(sa2_reader))
+}
+
+#[cfg(not(windows))]
+fn open_sa2(_exe_name: Option<String>) -> Result<Box<dyn InputReader>> {
+    Err(Error::ProcessError("Process memory reading only available on Windows"))
 }
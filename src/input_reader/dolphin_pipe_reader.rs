@@ -0,0 +1,257 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::cancel::CancelToken;
+use crate::controller_state::ControllerState;
+use crate::error::{Error, Result};
+use crate::input_reader::InputReader;
+use crate::output_sink::dolphin_pipe_sink::PIPE_BUTTONS;
+
+const POLL_INTERVAL: Duration = Duration::from_micros(1_000_000 / 120);
+
+/// Reads the text commands Dolphin takes through its input pipes, so scripts
+/// and bots that already drive Dolphin can drive the viewer too.
+pub struct DolphinPipeReader {
+    pipe: File,
+    pending: String,
+    state: ControllerState,
+    connected: bool,
+    cancel: CancelToken,
+}
+
+impl DolphinPipeReader {
+    pub fn from_path(path: &str) -> Result<DolphinPipeReader> {
+        let pipe = open_pipe(Path::new(path))?;
+        info!("Reading Dolphin pipe commands from {}", path);
+
+        Ok(DolphinPipeReader {
+            pipe: pipe,
+            pending: String::new(),
+            state: ControllerState::default(),
+            connected: false,
+            cancel: CancelToken::new(),
+        })
+    }
+
+    /// Reads whatever has been written so far without waiting for more.
+    /// Returns false if nothing has the pipe open for writing.
+    fn read_available(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.pipe.read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(len) => self.pending.push_str(&String::from_utf8_lossy(&buf[..len])),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn run_pending_commands(&mut self) {
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..end + 1).collect();
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Err(e) = run_command(&mut self.state, line) {
+                warn!("Bad Dolphin pipe command \"{}\": {}", line, e);
+            }
+        }
+    }
+}
+
+impl InputReader for DolphinPipeReader {
+    fn read_next_input(&mut self) -> Result<ControllerState> {
+        if self.cancel.sleep(POLL_INTERVAL) {
+            return Err(Error::Cancelled);
+        }
+
+        let connected = self.read_available()?;
+        self.run_pending_commands();
+        if connected != self.connected {
+            if connected {
+                info!("Something started writing to the Dolphin pipe");
+            }
+            else {
+                info!("Nothing is writing to the Dolphin pipe");
+                // Hold on to the state, but not half a command that will
+                // never be finished.
+                self.pending.clear();
+            }
+            self.connected = connected;
+        }
+
+        Ok(self.state)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+}
+
+/// Applies one `PRESS`, `RELEASE`, or `SET` command to `state`.
+pub fn run_command(state: &mut ControllerState, line: &str) -> Result<()> {
+    let words: Vec<String> = line.split_whitespace().map(|word| word.to_uppercase()).collect();
+    let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+    match words.as_slice() {
+        ["PRESS", name] | ["RELEASE", name] => {
+            let &(button, _) = PIPE_BUTTONS.iter()
+                .find(|&&(_, pipe_name)| pipe_name == *name)
+                .ok_or_else(|| Error::FormatError(format!("unknown button {}", name)))?;
+            state.set_button(button, words[0] == "PRESS");
+        }
+        ["SET", "MAIN", x, y] => state.analog = (stick_raw(x)?, stick_raw(y)?),
+        ["SET", "C", x, y] => state.c = (stick_raw(x)?, stick_raw(y)?),
+        ["SET", "L", value] => state.l_analog = trigger_raw(value)?,
+        ["SET", "R", value] => state.r_analog = trigger_raw(value)?,
+        _ => return Err(Error::FormatError("unknown command".to_string())),
+    }
+    Ok(())
+}
+
+fn parse_value(value: &str) -> Result<f32> {
+    value.parse::<f32>()
+        .map(|value| value.max(0.0).min(1.0))
+        .map_err(|_| Error::FormatError(format!("bad value {}", value)))
+}
+
+/// Sticks go from 0 to 1 with 0.5 in the center.
+fn stick_raw(value: &str) -> Result<u8> {
+    let value = parse_value(value)?;
+    Ok((128.0 + (value - 0.5) * 254.0).round().max(0.0).min(255.0) as u8)
+}
+
+/// Triggers go from 0 to 1.
+fn trigger_raw(value: &str) -> Result<u8> {
+    Ok((parse_value(value)? * 255.0).round() as u8)
+}
+
+/// Opens the pipe so reads never wait, since whatever writes to it might
+/// not be running yet.
+#[cfg(unix)]
+fn open_pipe(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_pipe(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).open(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::env;
+    use std::ffi::CString;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+
+    fn run(state: &mut ControllerState, line: &str) {
+        run_command(state, line).unwrap();
+    }
+
+    /// Makes a FIFO for something to write commands to.
+    fn make_fifo(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gc_input_{}_{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pipe");
+        let _ = fs::remove_file(&path);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        path
+    }
+
+    #[test]
+    fn presses_and_releases_buttons() {
+        let mut state = ControllerState::default();
+        run(&mut state, "PRESS A");
+        run(&mut state, "press d_up");
+        assert!(state.a);
+        assert!(state.up);
+
+        run(&mut state, "RELEASE A");
+        assert_eq!(state, ControllerState { up: true, ..ControllerState::default() });
+    }
+
+    #[test]
+    fn sets_sticks_and_triggers() {
+        let mut state = ControllerState::default();
+        run(&mut state, "SET MAIN 1 0");
+        run(&mut state, "SET C 0.5 0.5");
+        run(&mut state, "SET L 1");
+        run(&mut state, "SET R 0.5");
+        assert_eq!(state.analog, (255, 1));
+        assert_eq!(state.c, (128, 128));
+        assert_eq!(state.l_analog, 255);
+        assert_eq!(state.r_analog, 128);
+    }
+
+    #[test]
+    fn clamps_values_outside_0_to_1() {
+        let mut state = ControllerState::default();
+        run(&mut state, "SET MAIN -1 7");
+        run(&mut state, "SET L 2");
+        run(&mut state, "SET R -0.5");
+        assert_eq!(state.analog, (1, 255));
+        assert_eq!(state.l_analog, 255);
+        assert_eq!(state.r_analog, 0);
+    }
+
+    #[test]
+    fn rejects_bad_commands_without_changing_anything() {
+        let mut state = ControllerState::default();
+        for line in &["PRESS Q", "PRESS", "SET MAIN 0.5", "SET L x", "SET Z 1", "JUMP A"] {
+            assert!(run_command(&mut state, line).is_err(), "{}", line);
+        }
+        assert_eq!(state, ControllerState::default());
+    }
+
+    #[test]
+    fn holds_the_state_after_the_writer_goes_away() {
+        let path = make_fifo("pipe_reader");
+        let mut reader = DolphinPipeReader::from_path(path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.read_next_input().unwrap(), ControllerState::default());
+        assert!(!reader.is_connected());
+
+        let mut writer = OpenOptions::new().write(true).open(&path).unwrap();
+        writer.write_all(b"PRESS A\nSET MAIN 1 0\nbad\n").unwrap();
+        let state = reader.read_next_input().unwrap();
+        assert!(reader.is_connected());
+        assert!(state.a);
+        assert_eq!(state.analog, (255, 1));
+
+        // Half a command, then the writer goes away.
+        writer.write_all(b"PRESS").unwrap();
+        drop(writer);
+        assert_eq!(reader.read_next_input().unwrap(), state);
+        assert!(!reader.is_connected());
+
+        // The unfinished command was dropped rather than finished by the
+        // next writer.
+        let mut writer = OpenOptions::new().write(true).open(&path).unwrap();
+        writer.write_all(b" B\n").unwrap();
+        assert_eq!(reader.read_next_input().unwrap(), state);
+        assert!(reader.is_connected());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,19 +1,23 @@
 use std::io::BufReader;
 use std::fs::File;
-use std::thread;
 use std::time::{Instant, Duration};
 
 use dtm2txt::decoder::dtm_decoder::DtmDecoder;
 use dtm2txt::dtm::Dtm;
+use log::info;
 
-use crate::error::Result;
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
 use crate::input_reader::InputReader;
 use crate::controller_state::ControllerState;
+use crate::metadata::Metadata;
 
 pub struct DtmReader {
     dtm: Dtm,
     frame: usize,
+    current_frame: usize,
     playback_start: Option<Instant>,
+    cancel: CancelToken,
 }
 
 impl DtmReader {
@@ -25,13 +29,17 @@ impl DtmReader {
         Ok(DtmReader {
             dtm: dtm,
             frame: 0,
+            current_frame: 0,
             playback_start: None,
+            cancel: CancelToken::new(),
         })
     }
 
     pub fn from_path(path: &str) -> Result<DtmReader> {
         let file = File::open(path)?;
-        Self::from_file(file)
+        let reader = Self::from_file(file)?;
+        info!("Opened dtm file {} with {} frames", path, reader.dtm.controller_data.len());
+        Ok(reader)
     }
 }
 
@@ -43,12 +51,15 @@ impl InputReader for DtmReader {
         let wait_till = self.playback_start.unwrap() + (Duration::new(1, 0) * self.frame as u32) / 60;
         let now = Instant::now();
 
-        if wait_till > now {
-            thread::sleep(wait_till.duration_since(now));
+        if wait_till > now && self.cancel.sleep(wait_till.duration_since(now)) {
+            return Err(Error::Cancelled);
         }
 
         let mut state = ControllerState::default();
-        let current = self.dtm.controller_data[self.frame];
+        let current = self.dtm.controller_data.get(self.frame)
+            .cloned()
+            .ok_or_else(|| Error::FormatError("dtm file has no input frames".to_string()))?;
+        self.current_frame = self.frame + 1;
 
         state.a = current.a;
         state.b = current.b;
@@ -73,4 +84,15 @@ impl InputReader for DtmReader {
 
         Ok(state)
     }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        metadata.insert("dtm.frame".to_string(), self.current_frame.into());
+        metadata.insert("dtm.total_frames".to_string(), self.dtm.controller_data.len().into());
+        metadata
+    }
 }
\ No newline at end of file
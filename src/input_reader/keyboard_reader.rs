@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cancel::CancelToken;
+use crate::configuration::{KeyboardConf, StickKeys};
+use crate::controller_state::{Button, ControllerState};
+use crate::error::{Error, Result};
+use crate::input_reader::InputReader;
+
+const POLL_INTERVAL: Duration = Duration::from_micros(1_000_000 / 60);
+const DEFAULT_TILT: f32 = 0.5;
+// Dolphin's default keyboard layout, except for the D-pad, so the viewer's
+// H hotkey still works.
+const DEFAULT_BUTTONS: [(Button, &'static str); 10] = [
+    (Button::A, "X"),
+    (Button::B, "Z"),
+    (Button::X, "C"),
+    (Button::Y, "S"),
+    (Button::Z, "D"),
+    (Button::Start, "Return"),
+    (Button::Up, "Keypad 8"),
+    (Button::Down, "Keypad 2"),
+    (Button::Left, "Keypad 4"),
+    (Button::Right, "Keypad 6"),
+];
+
+/// Keys held down and mouse drags in the viewer window. The window writes to
+/// this and `KeyboardReader` turns it into controller input.
+#[derive(Clone, Debug, Default)]
+pub struct KeyboardInput {
+    /// Names of the keys being held, in lowercase.
+    keys: HashSet<String>,
+    /// Names of the keys the keyboard input method uses, in lowercase, so
+    /// the window knows not to treat them as hotkeys.
+    bound: HashSet<String>,
+    /// Whether a key name is one the window can send. Without it, any name
+    /// is taken.
+    key_check: Option<fn(&str) -> bool>,
+    /// How far the mouse has been dragged for each stick, from -1 to 1 with
+    /// up being positive.
+    pub analog_drag: Option<(f32, f32)>,
+    pub c_drag: Option<(f32, f32)>,
+}
+
+impl KeyboardInput {
+    /// Makes keyboard input that only takes the key names `check` accepts,
+    /// so typos in the config don't just leave a button that can't be
+    /// pressed.
+    pub fn with_key_check(check: fn(&str) -> bool) -> KeyboardInput {
+        KeyboardInput {
+            key_check: Some(check),
+            ..KeyboardInput::default()
+        }
+    }
+
+    pub fn press(&mut self, key: &str) {
+        self.keys.insert(key.to_lowercase());
+    }
+
+    pub fn release(&mut self, key: &str) {
+        self.keys.remove(&key.to_lowercase());
+    }
+
+    pub fn is_held(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn is_bound(&self, key: &str) -> bool {
+        self.bound.contains(&key.to_lowercase())
+    }
+
+    /// Lets go of everything, for when the window loses focus and won't hear
+    /// about keys being released.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.analog_drag = None;
+        self.c_drag = None;
+    }
+}
+
+/// Keys for each direction of a stick, in lowercase.
+#[derive(Clone, Debug)]
+struct StickBinding {
+    up: String,
+    down: String,
+    left: String,
+    right: String,
+}
+
+impl StickBinding {
+    fn from_conf(conf: Option<&StickKeys>, default: [&str; 4], check: KeyCheck) -> Result<StickBinding> {
+        Ok(StickBinding {
+            up: key_or(conf.and_then(|c| c.up.as_ref()), default[0], check)?,
+            down: key_or(conf.and_then(|c| c.down.as_ref()), default[1], check)?,
+            left: key_or(conf.and_then(|c| c.left.as_ref()), default[2], check)?,
+            right: key_or(conf.and_then(|c| c.right.as_ref()), default[3], check)?,
+        })
+    }
+
+    fn keys(&self) -> [&String; 4] {
+        [&self.up, &self.down, &self.left, &self.right]
+    }
+
+    /// Where the keys being held push the stick, from -1 to 1. Diagonals are
+    /// kept on the edge of the circle like a real stick's gate.
+    fn position(&self, input: &KeyboardInput) -> (f32, f32) {
+        let axis = |positive: &str, negative: &str| {
+            input.is_held(positive) as i32 as f32 - input.is_held(negative) as i32 as f32
+        };
+        let x = axis(&self.right, &self.left);
+        let y = axis(&self.up, &self.down);
+        let magnitude = x.hypot(y);
+        if magnitude > 1.0 {
+            (x / magnitude, y / magnitude)
+        }
+        else {
+            (x, y)
+        }
+    }
+}
+
+/// Makes controller input out of the keyboard and mouse in the viewer window,
+/// for trying out themes without a controller.
+pub struct KeyboardReader {
+    input: Arc<Mutex<KeyboardInput>>,
+    buttons: Vec<(Button, String)>,
+    analog: StickBinding,
+    c: StickBinding,
+    l: String,
+    r: String,
+    tilt: String,
+    tilt_amount: f32,
+    cancel: CancelToken,
+}
+
+impl KeyboardReader {
+    pub fn from_conf(conf: &KeyboardConf, input: Arc<Mutex<KeyboardInput>>) -> Result<KeyboardReader> {
+        let check = input.lock().unwrap_or_else(|e| e.into_inner()).key_check;
+        let buttons = match conf.buttons {
+            Some(ref buttons) => {
+                let mut parsed = Vec::new();
+                for (name, key) in buttons.iter() {
+                    let button = Button::from_name(name)
+                        .ok_or_else(|| Error::FormatError(format!("unknown button \"{}\" in keyboard keys", name)))?;
+                    parsed.push((button, key_name(key, check)?));
+                }
+                parsed
+            }
+            None => DEFAULT_BUTTONS.iter()
+                .map(|&(button, key)| (button, key.to_lowercase()))
+                .collect(),
+        };
+
+        let reader = KeyboardReader {
+            input: input,
+            buttons: buttons,
+            analog: StickBinding::from_conf(conf.analog.as_ref(), ["Up", "Down", "Left", "Right"], check)?,
+            c: StickBinding::from_conf(conf.c.as_ref(), ["I", "K", "J", "L"], check)?,
+            l: key_or(conf.l.as_ref(), "Q", check)?,
+            r: key_or(conf.r.as_ref(), "W", check)?,
+            tilt: key_or(conf.tilt.as_ref(), "Left Shift", check)?,
+            tilt_amount: conf.tilt_amount.unwrap_or(DEFAULT_TILT).max(0.0).min(1.0),
+            cancel: CancelToken::new(),
+        };
+
+        let mut bound: HashSet<String> = reader.buttons.iter().map(|&(_, ref key)| key.clone()).collect();
+        bound.extend(reader.analog.keys().iter().map(|&key| key.clone()));
+        bound.extend(reader.c.keys().iter().map(|&key| key.clone()));
+        bound.extend([&reader.l, &reader.r, &reader.tilt].iter().map(|&key| key.clone()));
+        reader.input.lock().unwrap_or_else(|e| e.into_inner()).bound = bound;
+        Ok(reader)
+    }
+}
+
+type KeyCheck = Option<fn(&str) -> bool>;
+
+/// Checks `key` with the window's key check, if there is one.
+fn key_name(key: &str, check: KeyCheck) -> Result<String> {
+    if check.map_or(true, |check| check(key)) {
+        Ok(key.to_lowercase())
+    }
+    else {
+        Err(Error::FormatError(format!("unknown key \"{}\" in keyboard keys", key)))
+    }
+}
+
+fn key_or(key: Option<&String>, default: &str, check: KeyCheck) -> Result<String> {
+    key_name(key.map(|k| k.as_str()).unwrap_or(default), check)
+}
+
+impl InputReader for KeyboardReader {
+    fn read_next_input(&mut self) -> Result<ControllerState> {
+        if self.cancel.sleep(POLL_INTERVAL) {
+            return Err(Error::Cancelled);
+        }
+
+        let input = self.input.lock().unwrap_or_else(|e| e.into_inner());
+        let mut state = ControllerState::default();
+        for &(button, ref key) in self.buttons.iter() {
+            if input.is_held(key) {
+                state.set_button(button, true);
+            }
+        }
+
+        // Holding the tilt key moves the sticks part of the way and presses
+        // the triggers lightly, without clicking them.
+        let tilted = input.is_held(&self.tilt);
+        let scale = if tilted { self.tilt_amount } else { 1.0 };
+        let analog = input.analog_drag.unwrap_or_else(|| self.analog.position(&input));
+        let c = input.c_drag.unwrap_or_else(|| self.c.position(&input));
+        state.analog = stick_to_raw(analog, scale);
+        state.c = stick_to_raw(c, scale);
+
+        if input.is_held(&self.l) {
+            state.l_analog = (255.0 * scale).round() as u8;
+            state.l_digital = state.l_digital || !tilted;
+        }
+        if input.is_held(&self.r) {
+            state.r_analog = (255.0 * scale).round() as u8;
+            state.r_digital = state.r_digital || !tilted;
+        }
+
+        Ok(state)
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+}
+
+fn stick_to_raw(position: (f32, f32), scale: f32) -> (u8, u8) {
+    let axis = |value: f32| (128.0 + value.max(-1.0).min(1.0) * scale * 127.0).round() as u8;
+    (axis(position.0), axis(position.1))
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+use log::info;
+
+use crate::cancel::CancelToken;
+use crate::controller_state::{Button, ControllerState};
+use crate::error::{Error, Result};
+use crate::input_reader::InputReader;
+use crate::input_reader::serial_capture::CaptureSource;
+use crate::input_reader::serial_reader::FrameReader;
+use crate::metadata::Metadata;
+
+/// Plays back a serial capture with its original timing, parsing it the same
+/// way as a live serial port.
+pub struct ReplayReader {
+    source: BufReader<CaptureSource<BufReader<File>>>,
+    frames: FrameReader,
+    last: ControllerState,
+    finished: bool,
+    cancel: CancelToken,
+}
+
+impl ReplayReader {
+    pub fn from_path(path: &str) -> Result<ReplayReader> {
+        let (source, decoder) = CaptureSource::open(path)?;
+        info!("Replaying serial capture {}", path);
+
+        Ok(ReplayReader {
+            source: BufReader::new(source),
+            frames: FrameReader::new(decoder, None),
+            last: ControllerState::default(),
+            finished: false,
+            cancel: CancelToken::new(),
+        })
+    }
+}
+
+impl InputReader for ReplayReader {
+    fn read_next_input(&mut self) -> Result<ControllerState> {
+        if !self.finished {
+            let frame = match self.frames.read_frame(&mut self.source) {
+                Err(_) if self.cancel.is_cancelled() => return Err(Error::Cancelled),
+                result => result?,
+            };
+            match frame {
+                Some(state) => {
+                    self.last = state;
+                    return Ok(state);
+                }
+                None => {
+                    info!("Serial capture finished");
+                    self.finished = true;
+                }
+            }
+        }
+
+        // Hold the last state once the capture is over.
+        if self.cancel.sleep(Duration::new(0, 1_000_000_000u32 / 60)) {
+            return Err(Error::Cancelled);
+        }
+        Ok(self.last)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        self.frames.add_metadata(&mut metadata);
+        metadata
+    }
+
+    fn buttons(&self) -> &'static [Button] {
+        self.frames.buttons()
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.source.get_mut().set_cancel_token(cancel.clone());
+        self.cancel = cancel;
+    }
+}
@@ -1,10 +1,12 @@
 mod process_reader;
 
-use std::thread;
 use std::time::Duration;
 
+use log::{info, warn};
+
 use crate::controller_state::ControllerState;
-use crate::error::Result;
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
 use crate::input_reader::InputReader;
 use crate::input_reader::sa2_reader::process_reader::ProcessHandle;
 
@@ -20,6 +22,7 @@ const R_ANALOG_ADDR: u64 = 0x0000000001A52C64;
 pub struct Sa2Reader {
     phandle: Option<ProcessHandle>,
     exe_name: Option<String>,
+    cancel: CancelToken,
 }
 
 impl Sa2Reader {
@@ -36,6 +39,7 @@ impl Sa2Reader {
         Ok(Sa2Reader {
             phandle: phandle,
             exe_name: exe_name,
+            cancel: CancelToken::new(),
         })
     }
 
@@ -75,7 +79,9 @@ impl Sa2Reader {
 impl InputReader for Sa2Reader {
     fn read_next_input(&mut self) -> Result<ControllerState> {
         if self.phandle.is_none() {
-            thread::sleep(Duration::from_secs(1));
+            if self.cancel.sleep(Duration::from_secs(1)) {
+                return Err(Error::Cancelled);
+            }
             self.phandle = ProcessHandle::from_name_filter(|pname| {
                 if let Some(ref name) = self.exe_name {
                     pname == *name
@@ -84,17 +90,31 @@ impl InputReader for Sa2Reader {
                     pname.to_lowercase() == SONIC_2_APP_EXE
                 }
             })?;
+            if self.phandle.is_some() {
+                info!("Found SA2 process");
+            }
             return Ok(ControllerState::default());
         }
 
-        thread::sleep(Duration::from_micros(1000000 / 120));
+        if self.cancel.sleep(Duration::from_micros(1000000 / 120)) {
+            return Err(Error::Cancelled);
+        }
 
         match self.get_controller_state() {
             Ok(controller_state) => Ok(controller_state),
-            Err(_) => {
+            Err(e) => {
+                warn!("Lost SA2 process: {}", e);
                 self.phandle = None;
                 Ok(ControllerState::default())
             }
         }
     }
+
+    fn is_connected(&self) -> bool {
+        self.phandle.is_some()
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
 }
@@ -0,0 +1,408 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::cancel::CancelToken;
+use crate::configuration::SdlConf;
+use crate::controller_state::{Button, ControllerState};
+use crate::error::{Error, Result};
+use crate::input_reader::InputReader;
+use crate::metadata::{Metadata, Value};
+
+const POLL_INTERVAL: Duration = Duration::from_micros(1_000_000 / 120);
+// SDL's names for its controller axes and buttons, in SDL's order.
+const AXIS_NAMES: [&'static str; 6] = [
+    "leftx", "lefty", "rightx", "righty", "lefttrigger", "righttrigger",
+];
+const BUTTON_NAMES: [&'static str; 15] = [
+    "a", "b", "x", "y", "back", "guide", "start", "leftstick", "rightstick",
+    "leftshoulder", "rightshoulder", "dpup", "dpdown", "dpleft", "dpright",
+];
+const DEFAULT_BUTTONS: [(Button, PadInput); 10] = [
+    (Button::A, PadInput::Button(PadButton::A)),
+    (Button::B, PadInput::Button(PadButton::B)),
+    (Button::X, PadInput::Button(PadButton::X)),
+    (Button::Y, PadInput::Button(PadButton::Y)),
+    (Button::Z, PadInput::Button(PadButton::RightShoulder)),
+    (Button::Start, PadInput::Button(PadButton::Start)),
+    (Button::Up, PadInput::Button(PadButton::DPadUp)),
+    (Button::Down, PadInput::Button(PadButton::DPadDown)),
+    (Button::Left, PadInput::Button(PadButton::DPadLeft)),
+    (Button::Right, PadInput::Button(PadButton::DPadRight)),
+];
+// Analog X and Y, C X and Y, then L and R.
+const DEFAULT_AXES: [PadAxis; 6] = [
+    PadAxis::Axis(Axis::LeftX),
+    PadAxis::Axis(Axis::LeftY),
+    PadAxis::Axis(Axis::RightX),
+    PadAxis::Axis(Axis::RightY),
+    PadAxis::Axis(Axis::TriggerLeft),
+    PadAxis::Axis(Axis::TriggerRight),
+];
+// Pads SDL doesn't know the layout of get their inputs in order, with the
+// D-pad on the first hat.
+const RAW_BUTTONS: [(Button, PadInput); 10] = [
+    (Button::A, PadInput::Numbered(0)),
+    (Button::B, PadInput::Numbered(1)),
+    (Button::X, PadInput::Numbered(2)),
+    (Button::Y, PadInput::Numbered(3)),
+    (Button::Z, PadInput::Numbered(4)),
+    (Button::Start, PadInput::Numbered(5)),
+    (Button::Up, PadInput::Hat(0, 0x01)),
+    (Button::Down, PadInput::Hat(0, 0x04)),
+    (Button::Left, PadInput::Hat(0, 0x08)),
+    (Button::Right, PadInput::Hat(0, 0x02)),
+];
+const RAW_AXES: [PadAxis; 6] = [
+    PadAxis::Numbered(0),
+    PadAxis::Numbered(1),
+    PadAxis::Numbered(2),
+    PadAxis::Numbered(3),
+    PadAxis::Numbered(4),
+    PadAxis::Numbered(5),
+];
+// How far in a trigger has to be to count as clicked, from 0 to 1.
+const DEFAULT_CLICK: f32 = 0.95;
+
+/// SDL's game controller axes, numbered the same way SDL does.
+#[derive(Clone, Copy, Debug)]
+enum Axis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    TriggerLeft,
+    TriggerRight,
+}
+
+impl Axis {
+    const ALL: [Axis; 6] = [
+        Axis::LeftX,
+        Axis::LeftY,
+        Axis::RightX,
+        Axis::RightY,
+        Axis::TriggerLeft,
+        Axis::TriggerRight,
+    ];
+
+    fn from_name(name: &str) -> Option<Axis> {
+        let name = name.to_lowercase();
+        AXIS_NAMES.iter().position(|&axis| axis == name).map(|index| Self::ALL[index])
+    }
+}
+
+/// SDL's game controller buttons, numbered the same way SDL does.
+#[derive(Clone, Copy, Debug)]
+enum PadButton {
+    A,
+    B,
+    X,
+    Y,
+    Back,
+    Guide,
+    Start,
+    LeftStick,
+    RightStick,
+    LeftShoulder,
+    RightShoulder,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl PadButton {
+    const ALL: [PadButton; 15] = [
+        PadButton::A,
+        PadButton::B,
+        PadButton::X,
+        PadButton::Y,
+        PadButton::Back,
+        PadButton::Guide,
+        PadButton::Start,
+        PadButton::LeftStick,
+        PadButton::RightStick,
+        PadButton::LeftShoulder,
+        PadButton::RightShoulder,
+        PadButton::DPadUp,
+        PadButton::DPadDown,
+        PadButton::DPadLeft,
+        PadButton::DPadRight,
+    ];
+
+    fn from_name(name: &str) -> Option<PadButton> {
+        let name = name.to_lowercase();
+        BUTTON_NAMES.iter().position(|&button| button == name).map(|index| Self::ALL[index])
+    }
+}
+
+/// A button to read from a pad, written the same way as in SDL mappings:
+/// one of SDL's controller buttons, or a raw joystick's numbered button
+/// (`b3`) or hat direction (`h0.4`).
+#[derive(Clone, Copy, Debug)]
+enum PadInput {
+    Button(PadButton),
+    Numbered(usize),
+    Hat(usize, u8),
+}
+
+impl PadInput {
+    fn from_name(name: &str) -> Option<PadInput> {
+        if let Some(button) = PadButton::from_name(name) {
+            Some(PadInput::Button(button))
+        }
+        else if name.starts_with('b') {
+            name[1..].parse().ok().map(PadInput::Numbered)
+        }
+        else if name.starts_with('h') {
+            let mut parts = name[1..].splitn(2, '.');
+            let hat = parts.next()?.parse().ok()?;
+            let mask = parts.next()?.parse().ok()?;
+            Some(PadInput::Hat(hat, mask))
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// An axis to read from a pad: one of SDL's controller axes, or a raw
+/// joystick's numbered axis (`a2`).
+#[derive(Clone, Copy, Debug)]
+enum PadAxis {
+    Axis(Axis),
+    Numbered(usize),
+}
+
+impl PadAxis {
+    fn from_name(name: &str) -> Option<PadAxis> {
+        if let Some(axis) = Axis::from_name(name) {
+            Some(PadAxis::Axis(axis))
+        }
+        else if name.starts_with('a') {
+            name[1..].parse().ok().map(PadAxis::Numbered)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// The last values read from a connected pad.
+#[derive(Clone, Debug)]
+pub struct PadState {
+    pub instance_id: i32,
+    pub name: String,
+    pub guid: String,
+    /// Whether SDL knows the pad's layout. Pads it doesn't are read as raw
+    /// joysticks, with numbered axes, buttons and hats.
+    pub mapped: bool,
+    /// Axis values, in the same order as SDL's axes for mapped pads.
+    pub axes: Vec<i16>,
+    /// Button values, in the same order as SDL's buttons for mapped pads.
+    pub buttons: Vec<bool>,
+    /// Hat positions as SDL's direction bits. Only raw joysticks have these.
+    pub hats: Vec<u8>,
+}
+
+impl PadState {
+    /// SDL's named inputs only work on mapped pads, and numbered ones only
+    /// on raw joysticks.
+    fn axis(&self, axis: PadAxis) -> Option<i16> {
+        match axis {
+            PadAxis::Axis(axis) if self.mapped => self.axes.get(axis as usize).cloned(),
+            PadAxis::Numbered(index) if !self.mapped => self.axes.get(index).cloned(),
+            _ => None,
+        }
+    }
+
+    fn button(&self, input: PadInput) -> bool {
+        match input {
+            PadInput::Button(button) if self.mapped => self.buttons.get(button as usize).cloned().unwrap_or(false),
+            PadInput::Numbered(index) if !self.mapped => self.buttons.get(index).cloned().unwrap_or(false),
+            PadInput::Hat(index, mask) if !self.mapped => self.hats.get(index).map_or(false, |&hat| hat & mask != 0),
+            _ => false,
+        }
+    }
+}
+
+/// Pads SDL knows about, kept up to date by whichever thread owns SDL and
+/// read by `SdlReader`. The library never touches SDL itself.
+#[derive(Clone, Debug, Default)]
+pub struct SdlPads {
+    /// Connected pads, in the order they were plugged in.
+    pub pads: Vec<PadState>,
+    /// Controller mappings readers want added.
+    pending_mappings: Vec<String>,
+}
+
+impl SdlPads {
+    /// Hands over the controller mappings readers have asked for since last
+    /// time, for the thread that owns SDL to add.
+    pub fn take_mappings(&mut self) -> Vec<String> {
+        self.pending_mappings.drain(..).collect()
+    }
+}
+
+/// Reads any joystick or gamepad SDL supports, including GameCube adapters
+/// in PC mode. Something has to keep its `SdlPads` up to date from SDL,
+/// like the viewer does.
+pub struct SdlReader {
+    pads: Arc<Mutex<SdlPads>>,
+    index: usize,
+    guid: Option<String>,
+    /// Buttons and axes from the config. Anything left out uses the defaults
+    /// for whichever kind of pad is being read.
+    buttons: Option<Vec<(Button, PadInput)>>,
+    axes: [Option<PadAxis>; 6],
+    click: f32,
+    connected: Option<String>,
+    cancel: CancelToken,
+}
+
+impl SdlReader {
+    pub fn from_conf(conf: &SdlConf, pads: Arc<Mutex<SdlPads>>) -> Result<SdlReader> {
+        let buttons = match conf.buttons {
+            Some(ref buttons) => {
+                let mut parsed = Vec::new();
+                for (name, pad_name) in buttons.iter() {
+                    let button = Button::from_name(name)
+                        .ok_or_else(|| Error::FormatError(format!("unknown button \"{}\" in sdl buttons", name)))?;
+                    let pad_button = PadInput::from_name(pad_name)
+                        .ok_or_else(|| Error::FormatError(format!("unknown SDL button \"{}\"", pad_name)))?;
+                    parsed.push((button, pad_button));
+                }
+                Some(parsed)
+            }
+            None => None,
+        };
+
+        let axes = conf.axes.clone().unwrap_or_default();
+        let axis = |name: Option<&String>| match name {
+            Some(name) => PadAxis::from_name(name)
+                .map(Some)
+                .ok_or_else(|| Error::FormatError(format!("unknown SDL axis \"{}\"", name))),
+            None => Ok(None),
+        };
+
+        if let Some(ref mapping) = conf.mapping {
+            pads.lock().unwrap_or_else(|e| e.into_inner()).pending_mappings.push(mapping.clone());
+        }
+
+        Ok(SdlReader {
+            pads: pads,
+            index: conf.index.unwrap_or(0),
+            guid: conf.guid.as_ref().map(|guid| guid.to_lowercase()),
+            buttons: buttons,
+            axes: [
+                axis(axes.analog_x.as_ref())?,
+                axis(axes.analog_y.as_ref())?,
+                axis(axes.c_x.as_ref())?,
+                axis(axes.c_y.as_ref())?,
+                axis(axes.l_analog.as_ref())?,
+                axis(axes.r_analog.as_ref())?,
+            ],
+            click: conf.click_threshold.unwrap_or(DEFAULT_CLICK),
+            connected: None,
+            cancel: CancelToken::new(),
+        })
+    }
+
+    fn find_pad<'a>(&self, pads: &'a [PadState]) -> Option<&'a PadState> {
+        match self.guid {
+            Some(ref guid) => pads.iter().find(|pad| pad.guid.to_lowercase() == *guid),
+            None => pads.get(self.index),
+        }
+    }
+}
+
+impl InputReader for SdlReader {
+    fn read_next_input(&mut self) -> Result<ControllerState> {
+        if self.cancel.sleep(POLL_INTERVAL) {
+            return Err(Error::Cancelled);
+        }
+
+        let pads = self.pads.lock().unwrap_or_else(|e| e.into_inner());
+        let pad = self.find_pad(&pads.pads);
+
+        let name = pad.map(|pad| pad.name.clone());
+        if name != self.connected {
+            match name {
+                Some(ref name) => info!("Reading from controller {}", name),
+                None => warn!("Controller disconnected"),
+            }
+            self.connected = name;
+        }
+
+        let pad = match pad {
+            Some(pad) => pad,
+            None => return Ok(ControllerState::default()),
+        };
+
+        let (default_buttons, default_axes) = if pad.mapped {
+            (&DEFAULT_BUTTONS, &DEFAULT_AXES)
+        }
+        else {
+            (&RAW_BUTTONS, &RAW_AXES)
+        };
+        let buttons = self.buttons.as_ref().map(|buttons| buttons.as_slice()).unwrap_or(default_buttons);
+        let mut axes = *default_axes;
+        for (axis, conf_axis) in axes.iter_mut().zip(self.axes.iter()) {
+            *axis = conf_axis.unwrap_or(*axis);
+        }
+
+        let mut state = ControllerState::default();
+        for &(button, input) in buttons.iter() {
+            if pad.button(input) {
+                state.set_button(button, true);
+            }
+        }
+        let stick = |axis: PadAxis, flip: bool| stick_to_raw(pad.axis(axis).unwrap_or(0), flip);
+        state.analog = (stick(axes[0], false), stick(axes[1], true));
+        state.c = (stick(axes[2], false), stick(axes[3], true));
+        state.l_analog = trigger_to_raw(pad.axis(axes[4]), axes[4]);
+        state.r_analog = trigger_to_raw(pad.axis(axes[5]), axes[5]);
+        state.l_digital = state.l_digital || state.l_analog as f32 >= self.click * 255.0;
+        state.r_digital = state.r_digital || state.r_analog as f32 >= self.click * 255.0;
+
+        Ok(state)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        if let Some(ref name) = self.connected {
+            metadata.insert("sdl.name".to_string(), Value::Text(name.clone()));
+        }
+        metadata
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.is_some()
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+}
+
+/// SDL's axes go from -32768 to 32767 with down being positive, so `flip` is
+/// set for the up and down axes.
+fn stick_to_raw(value: i16, flip: bool) -> u8 {
+    let value = (value as i32) >> 8;
+    let raw = if flip { 128 - value } else { 128 + value };
+    raw.max(0).min(255) as u8
+}
+
+/// Controller triggers go from 0 to 32767. Raw joysticks don't say which
+/// axes are triggers, so those use the whole range.
+fn trigger_to_raw(value: Option<i16>, axis: PadAxis) -> u8 {
+    let value = match value {
+        Some(value) => value as i32,
+        None => return 0,
+    };
+    match axis {
+        PadAxis::Axis(_) => (value.max(0) >> 7).min(255) as u8,
+        PadAxis::Numbered(_) => ((value + 32768) >> 8) as u8,
+    }
+}
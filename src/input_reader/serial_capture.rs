@@ -0,0 +1,337 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancelToken;
+use crate::configuration::{Console, SerialFraming};
+use crate::error::Result;
+use crate::input_reader::serial_reader::FrameDecoder;
+
+// Capture files start with this and a version byte, followed by the console,
+// framing and terminator the bytes were read with. Version 1 captures don't
+// have the console, since they were all from GameCube controllers. After
+// that come records of a little endian u64 of microseconds since the capture
+// started, a little endian u32 length, and that many bytes exactly as they
+// came from the port.
+const MAGIC: &'static [u8; 7] = b"GCIVCAP";
+const VERSION: u8 = 2;
+// Longest record written or read back. Serial reads are far shorter, and
+// anything longer in a capture means it's corrupt.
+const MAX_RECORD_LEN: usize = 4096;
+
+fn console_to_byte(console: Console) -> u8 {
+    match console {
+        Console::Gamecube => 0,
+        Console::N64 => 1,
+        Console::Snes => 2,
+        Console::Nes => 3,
+        Console::Classic => 4,
+    }
+}
+
+fn console_from_byte(byte: u8) -> Option<Console> {
+    match byte {
+        0 => Some(Console::Gamecube),
+        1 => Some(Console::N64),
+        2 => Some(Console::Snes),
+        3 => Some(Console::Nes),
+        4 => Some(Console::Classic),
+        _ => None,
+    }
+}
+
+fn framing_to_byte(framing: SerialFraming) -> u8 {
+    match framing {
+        SerialFraming::Ascii => 0,
+        SerialFraming::Binary => 1,
+        SerialFraming::Packed => 2,
+    }
+}
+
+fn framing_from_byte(byte: u8) -> Option<SerialFraming> {
+    match byte {
+        0 => Some(SerialFraming::Ascii),
+        1 => Some(SerialFraming::Binary),
+        2 => Some(SerialFraming::Packed),
+        _ => None,
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Saves bytes read from a serial port along with when they were read.
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create<P>(path: P, decoder: &FrameDecoder) -> Result<CaptureWriter>
+        where P: AsRef<Path>,
+    {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[
+            VERSION,
+            console_to_byte(decoder.console()),
+            framing_to_byte(decoder.framing()),
+            decoder.terminator(),
+        ])?;
+        file.flush()?;
+
+        Ok(CaptureWriter {
+            file: file,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let elapsed = self.start.elapsed();
+        let micros = elapsed.as_secs() * 1_000_000 + elapsed.subsec_micros() as u64;
+        for chunk in bytes.chunks(MAX_RECORD_LEN) {
+            self.file.write_all(&micros.to_le_bytes())?;
+            self.file.write_all(&(chunk.len() as u32).to_le_bytes())?;
+            self.file.write_all(chunk)?;
+        }
+        // Flush every record so a crash doesn't lose the part of the capture
+        // that's most likely to matter.
+        self.file.flush()
+    }
+}
+
+/// Plays back a capture as a stream of bytes, with each record held back
+/// until the same time after starting as when it was captured.
+pub struct CaptureSource<R> {
+    reader: R,
+    cancel: CancelToken,
+    start: Option<Instant>,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl CaptureSource<BufReader<File>> {
+    /// Opens a capture, returning it along with a decoder set up the same way
+    /// as the one it was captured with.
+    pub fn open<P>(path: P) -> Result<(CaptureSource<BufReader<File>>, FrameDecoder)>
+        where P: AsRef<Path>,
+    {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R> CaptureSource<R>
+    where R: Read,
+{
+    /// Reads a capture's header from `reader`, returning the capture along
+    /// with a decoder set up the same way as the one it was captured with.
+    pub fn from_reader(mut reader: R) -> Result<(CaptureSource<R>, FrameDecoder)> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic[..7] != MAGIC {
+            return Err(invalid_data("not a serial capture file").into());
+        }
+
+        let console = match magic[7] {
+            1 => Console::Gamecube,
+            2 => {
+                let mut console = [0];
+                reader.read_exact(&mut console)?;
+                console_from_byte(console[0])
+                    .ok_or_else(|| invalid_data("unknown console in serial capture"))?
+            }
+            _ => return Err(invalid_data("serial capture is from a newer version of the viewer").into()),
+        };
+        let mut settings = [0; 2];
+        reader.read_exact(&mut settings)?;
+        let framing = framing_from_byte(settings[0])
+            .ok_or_else(|| invalid_data("unknown framing in serial capture"))?;
+        let decoder = FrameDecoder::new(console, framing, settings[1]);
+
+        let source = CaptureSource {
+            reader: reader,
+            cancel: CancelToken::new(),
+            start: None,
+            pending: Vec::new(),
+            pos: 0,
+        };
+        Ok((source, decoder))
+    }
+
+    /// Lets `cancel` interrupt waiting for the next record, which makes
+    /// reading fail with `io::ErrorKind::Other`.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    /// Reads the next record and waits until it's time for it. Returns false
+    /// at the end of the capture.
+    fn next_record(&mut self) -> io::Result<bool> {
+        let mut header = [0; 12];
+        match self.reader.read_exact(&mut header) {
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            result => result?,
+        }
+
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&header[..8]);
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[8..]);
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(invalid_data("record in serial capture is too long"));
+        }
+        self.pending.resize(len, 0);
+        self.reader.read_exact(&mut self.pending)?;
+        self.pos = 0;
+
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let wait_till = start + Duration::from_micros(u64::from_le_bytes(micros));
+        let now = Instant::now();
+        if wait_till > now && self.cancel.sleep(wait_till.duration_since(now)) {
+            return Err(io::Error::new(io::ErrorKind::Other, "replay cancelled"));
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R> Read for CaptureSource<R>
+    where R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.pending.len() {
+            if !self.next_record()? {
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.pending.len() - self.pos);
+        buf[..len].copy_from_slice(&self.pending[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use std::process;
+
+    use super::*;
+    use crate::controller_state::ControllerState;
+    use crate::input_reader::serial_reader::FrameReader;
+
+    const FIXTURE: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gamecube_ascii_v1.bin");
+
+    /// What the fixture holds: a partial frame from connecting partway
+    /// through, two good frames with the second split across records, a
+    /// frame with the always-set bit cleared, and one more good frame.
+    fn fixture_states() -> Vec<ControllerState> {
+        let first = ControllerState {
+            a: true,
+            ..ControllerState::default()
+        };
+        let second = ControllerState {
+            b: true,
+            z: true,
+            up: true,
+            l_digital: true,
+            analog: (200, 60),
+            c: (30, 220),
+            l_analog: 255,
+            r_analog: 100,
+            ..ControllerState::default()
+        };
+        let third = ControllerState {
+            start: true,
+            ..ControllerState::default()
+        };
+        vec![first, second, third]
+    }
+
+    fn read_all<R>(reader: &mut FrameReader, port: &mut R) -> Vec<ControllerState>
+        where R: io::BufRead,
+    {
+        let mut states = Vec::new();
+        while let Some(state) = reader.read_frame(port).unwrap() {
+            states.push(state);
+        }
+        states
+    }
+
+    #[test]
+    fn replays_version_1_fixture_as_gamecube() {
+        let (source, decoder) = CaptureSource::open(FIXTURE).unwrap();
+        assert_eq!(decoder.console(), Console::Gamecube);
+        assert_eq!(decoder.framing(), SerialFraming::Ascii);
+        assert_eq!(decoder.terminator(), b'\n');
+
+        let mut reader = FrameReader::new(decoder, None);
+        let states = read_all(&mut reader, &mut BufReader::new(source));
+        assert_eq!(states, fixture_states());
+    }
+
+    #[test]
+    fn round_trips_through_capture_writer() {
+        let (mut source, decoder) = CaptureSource::open(FIXTURE).unwrap();
+        let mut raw = Vec::new();
+        source.read_to_end(&mut raw).unwrap();
+
+        let dir = env::temp_dir().join(format!("gc_input_capture_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.bin");
+        let capture = CaptureWriter::create(&path, &decoder).unwrap();
+        let mut reader = FrameReader::new(decoder, Some(capture));
+        let live_states = read_all(&mut reader, &mut Cursor::new(raw));
+        drop(reader);
+
+        let header = fs::read(&path).unwrap();
+        assert_eq!(&header[..8], b"GCIVCAP\x02");
+
+        let (replay, replay_decoder) = CaptureSource::open(&path).unwrap();
+        assert_eq!(replay_decoder.console(), decoder.console());
+        assert_eq!(replay_decoder.framing(), decoder.framing());
+        assert_eq!(replay_decoder.terminator(), decoder.terminator());
+        let mut reader = FrameReader::new(replay_decoder, None);
+        let replayed_states = read_all(&mut reader, &mut BufReader::new(replay));
+
+        assert_eq!(live_states, fixture_states());
+        assert_eq!(replayed_states, fixture_states());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_console_from_version_2_header() {
+        let (_, decoder) = CaptureSource::from_reader(Cursor::new(b"GCIVCAP\x02\x01\x01\x00".to_vec())).unwrap();
+        assert_eq!(decoder.console(), Console::N64);
+        assert_eq!(decoder.framing(), SerialFraming::Binary);
+        assert_eq!(decoder.terminator(), 0);
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        assert!(CaptureSource::from_reader(Cursor::new(b"GCIVCAP\x03\x00\x00\n".to_vec())).is_err());
+        assert!(CaptureSource::from_reader(Cursor::new(b"NOTACAP\x02\x00\x00\n".to_vec())).is_err());
+        assert!(CaptureSource::from_reader(Cursor::new(b"GCIVCAP\x02\x09\x00\n".to_vec())).is_err());
+    }
+
+    #[test]
+    fn rejects_records_that_are_too_long() {
+        let mut capture = b"GCIVCAP\x02\x00\x00\n".to_vec();
+        capture.extend_from_slice(&0u64.to_le_bytes());
+        capture.extend_from_slice(&0xffff_ffffu32.to_le_bytes());
+        let (mut source, _) = CaptureSource::from_reader(Cursor::new(capture)).unwrap();
+        let error = source.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
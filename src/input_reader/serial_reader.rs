@@ -1,55 +1,540 @@
-use std::io::{Read, BufRead, BufReader, ErrorKind};
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::mem;
+use std::result;
+use std::time::{Duration, Instant};
 
-use serialport::{SerialPort, SerialPortSettings, DataBits, FlowControl, Parity, StopBits};
+use log::{debug, info, warn};
+use serialport::{SerialPort, SerialPortSettings, SerialPortType, DataBits, FlowControl, Parity, StopBits};
 
-use crate::error::Result;
+use crate::cancel::CancelToken;
+use crate::configuration::{Console, SerialConf, SerialFraming, SerialParity};
+use crate::error::{Error, Result};
 use crate::input_reader::InputReader;
-use crate::controller_state::ControllerState;
+use crate::input_reader::serial_capture::CaptureWriter;
+use crate::controller_state::{Button, ControllerState};
+use crate::metadata::Metadata;
 
-pub struct SerialReader<S> {
-    port: BufReader<S>,
+const DEFAULT_BAUD_RATE: u32 = 115200;
+const DEFAULT_TIMEOUT_MS: u64 = 10;
+const DEFAULT_TERMINATOR: u8 = b'\n';
+const DEFAULT_RETRY_MS: u64 = 1000;
+
+// Path that makes the reader look for a port that's sending controller data.
+const AUTO_PATH: &'static str = "auto";
+// How long to listen to each port while looking, and how many good frames it
+// has to send in that time.
+const PROBE_TIME: Duration = Duration::from_millis(500);
+const PROBE_FRAMES: usize = 3;
+
+/// How a console's frames are laid out.
+struct FrameLayout {
+    /// Number of bits in a frame.
+    bits: usize,
+    /// Number of bytes in the controller's report when sent packed. This can
+    /// have a few more bits at the start than a frame does.
+    report_bytes: usize,
+    /// Bits that always have the same value, as (index, value).
+    constant_bits: &'static [(usize, bool)],
+}
+
+/// Buttons on each console's controller.
+fn console_buttons(console: Console) -> &'static [Button] {
+    match console {
+        Console::Gamecube => &Button::GAMECUBE,
+        Console::N64 => &[
+            Button::A, Button::B, Button::Z, Button::Start,
+            Button::Up, Button::Down, Button::Left, Button::Right,
+            Button::LDigital, Button::RDigital,
+            Button::CUp, Button::CDown, Button::CLeft, Button::CRight,
+        ],
+        Console::Snes => &[
+            Button::A, Button::B, Button::X, Button::Y, Button::Select, Button::Start,
+            Button::Up, Button::Down, Button::Left, Button::Right,
+            Button::LDigital, Button::RDigital,
+        ],
+        Console::Nes => &[
+            Button::A, Button::B, Button::Select, Button::Start,
+            Button::Up, Button::Down, Button::Left, Button::Right,
+        ],
+        Console::Classic => &[
+            Button::A, Button::B, Button::X, Button::Y, Button::Select, Button::Home, Button::Start,
+            Button::Up, Button::Down, Button::Left, Button::Right,
+            Button::LDigital, Button::RDigital, Button::Zl, Button::Zr,
+        ],
+    }
+}
+
+fn frame_layout(console: Console) -> FrameLayout {
+    match console {
+        // NintendoSpy firmware drops the first two bits of the report. After
+        // that, the bit just before L is always set by the controller. The
+        // bit before Start is the controller asking for its origin to be
+        // read, which can be either.
+        Console::Gamecube => FrameLayout { bits: 62, report_bytes: 8, constant_bits: &[(6, true)] },
+        // The bit after the reset bit is unused.
+        Console::N64 => FrameLayout { bits: 32, report_bytes: 4, constant_bits: &[(9, false)] },
+        Console::Snes => FrameLayout { bits: 16, report_bytes: 2, constant_bits: &[] },
+        Console::Nes => FrameLayout { bits: 8, report_bytes: 1, constant_bits: &[] },
+        // Buttons are active low and the last bit of the fifth byte is unused,
+        // so it always reads as released.
+        Console::Classic => FrameLayout { bits: 48, report_bytes: 6, constant_bits: &[(39, true)] },
+    }
 }
+// Number of recent frames the recent error rate is worked out over.
+const RECENT_FRAMES: usize = 1000;
 
-impl SerialReader<Box<dyn SerialPort>> {
-    pub fn from_path(path: &str) -> Result<SerialReader<Box<dyn SerialPort>>> {
+pub struct SerialReader {
+    port: Option<BufReader<Box<dyn SerialPort>>>,
+    conf: SerialConf,
+    settings: SerialPortSettings,
+    frames: FrameReader,
+    cancel: CancelToken,
+}
+
+impl SerialReader {
+    pub fn from_conf(conf: &SerialConf) -> Result<SerialReader> {
+        if conf.path.is_none() && (conf.vid.is_none() || conf.pid.is_none()) {
+            return Err(serialport::Error::new(serialport::ErrorKind::InvalidInput, "serial input needs a path or a vid and pid").into());
+        }
+
+        let parity = match conf.parity.unwrap_or(SerialParity::None) {
+            SerialParity::None => Parity::None,
+            SerialParity::Odd => Parity::Odd,
+            SerialParity::Even => Parity::Even,
+        };
         let s = SerialPortSettings {
-            baud_rate: 115200,
+            baud_rate: conf.baud_rate.unwrap_or(DEFAULT_BAUD_RATE),
             data_bits: DataBits::Eight,
             flow_control: FlowControl::None,
-            parity: Parity::None,
+            parity: parity,
             stop_bits: StopBits::One,
-            timeout: Duration::from_millis(10),
+            timeout: Duration::from_millis(conf.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+        };
+
+        let decoder = FrameDecoder::from_conf(conf);
+        let capture = match conf.capture.as_ref() {
+            Some(path) => {
+                info!("Capturing serial input to {}", path);
+                Some(CaptureWriter::create(path, &decoder)?)
+            }
+            None => None,
+        };
+
+        let mut reader = SerialReader {
+            port: None,
+            conf: conf.clone(),
+            settings: s,
+            frames: FrameReader::new(decoder, capture),
+            cancel: CancelToken::new(),
+        };
+        // Not finding the device yet is fine, since it gets retried later.
+        reader.connect();
+
+        Ok(reader)
+    }
+
+    /// Listens to a port for a bit and checks if it's sending frames this
+    /// reader can understand.
+    fn probe(&self, path: &str) -> bool {
+        let port = match serialport::open_with_settings(path, &self.settings) {
+            Ok(port) => port,
+            Err(_) => return false,
+        };
+        let mut port = BufReader::new(port);
+        let mut frames = FrameReader::new(self.frames.decoder, None);
+        let mut good_frames = 0;
+        let start = Instant::now();
+
+        while start.elapsed() < PROBE_TIME && !self.cancel.is_cancelled() {
+            match frames.read_frame(&mut port) {
+                Err(e) => {
+                    if let ErrorKind::TimedOut = e.kind() {
+                        continue;
+                    }
+                    return false;
+                }
+                Ok(None) => return false,
+                Ok(Some(_)) => {
+                    good_frames += 1;
+                    if good_frames >= PROBE_FRAMES {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    fn probe_ports(&self) -> Option<String> {
+        serialport::available_ports()
+            .unwrap_or_else(|_| Vec::new())
+            .into_iter()
+            .map(|port| port.port_name)
+            .find(|path| self.probe(path))
+    }
+
+    /// Picks the port matching the configured USB ids if there is one,
+    /// otherwise the configured path.
+    fn find_port(&self) -> Option<String> {
+        if let (Some(vid), Some(pid)) = (self.conf.vid, self.conf.pid) {
+            let found = serialport::available_ports()
+                .unwrap_or_else(|_| Vec::new())
+                .into_iter()
+                .find(|port| match port.port_type {
+                    SerialPortType::UsbPort(ref info) => info.vid == vid && info.pid == pid,
+                    _ => false,
+                });
+            if let Some(port) = found {
+                return Some(port.port_name);
+            }
+        }
+        match self.conf.path {
+            Some(ref path) if path == AUTO_PATH => self.probe_ports(),
+            ref path => path.clone(),
+        }
+    }
+
+    fn connect(&mut self) {
+        let path = match self.find_port() {
+            Some(path) => path,
+            None => return,
         };
-        let port = serialport::open_with_settings(path, &s)?;
+        match serialport::open_with_settings(&path, &self.settings) {
+            Ok(port) => {
+                info!("Connected to serial port {}", path);
+                self.port = Some(BufReader::new(port));
+                self.frames.reset();
+            }
+            Err(e) => warn!("Could not open serial port {}: {}", path, e),
+        }
+    }
 
-        Ok(SerialReader {
-            port: BufReader::new(port),
-        })
+    fn disconnect(&mut self, reason: &str) {
+        warn!("Serial port disconnected: {}", reason);
+        self.port = None;
+        self.frames.reset();
     }
 }
 
-impl<R> InputReader for SerialReader<R>
-    where R: Read
-{
+impl InputReader for SerialReader {
     fn read_next_input(&mut self) -> Result<ControllerState> {
-        let mut buf = String::new();
-
         loop {
-            buf.clear();
-            match self.port.read_line(&mut buf) {
+            if self.cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let port = match self.port.as_mut() {
+                Some(port) => port,
+                None => {
+                    let retry = Duration::from_millis(self.conf.retry_ms.unwrap_or(DEFAULT_RETRY_MS));
+                    if self.cancel.sleep(retry) {
+                        return Err(Error::Cancelled);
+                    }
+                    self.connect();
+                    return Ok(ControllerState::default());
+                }
+            };
+
+            match self.frames.read_frame(port) {
                 Err(e) => {
+                    // Whatever was read before the timeout stays buffered
+                    // and gets finished off next time around.
                     if let ErrorKind::TimedOut = e.kind() {
                         continue;
                     }
+                    self.disconnect(&e.to_string());
+                    return Ok(ControllerState::default());
+                }
+                Ok(None) => {
+                    self.disconnect("end of stream");
+                    return Ok(ControllerState::default());
+                }
+                Ok(Some(state)) => return Ok(state),
+            }
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        self.frames.add_metadata(&mut metadata);
+        metadata
+    }
+
+    fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    fn buttons(&self) -> &'static [Button] {
+        self.frames.buttons()
+    }
+
+    fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+}
+
+/// Counts of good and bad frames, for telling how healthy the connection is.
+#[derive(Clone, Debug, Default)]
+struct FrameStats {
+    good_frames: u64,
+    bad_frames: u64,
+    recent: VecDeque<bool>,
+}
+
+impl FrameStats {
+    fn record(&mut self, good: bool) {
+        if good {
+            self.good_frames += 1;
+        }
+        else {
+            self.bad_frames += 1;
+        }
+
+        self.recent.push_back(good);
+        if self.recent.len() > RECENT_FRAMES {
+            self.recent.pop_front();
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        let total = self.good_frames + self.bad_frames;
+        if total == 0 {
+            0.0
+        }
+        else {
+            self.bad_frames as f64 * 100.0 / total as f64
+        }
+    }
+
+    fn recent_error_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            0.0
+        }
+        else {
+            let bad = self.recent.iter().filter(|&&good| !good).count();
+            bad as f64 * 100.0 / self.recent.len() as f64
+        }
+    }
+
+    fn add_metadata(&self, metadata: &mut Metadata) {
+        metadata.insert("serial.good_frames".to_string(), self.good_frames.into());
+        metadata.insert("serial.bad_frames".to_string(), self.bad_frames.into());
+        metadata.insert("serial.error_rate".to_string(), self.error_rate().into());
+        metadata.insert("serial.recent_error_rate".to_string(), self.recent_error_rate().into());
+    }
+}
+
+/// Pulls frames out of a stream of bytes from a serial device, keeping count
+/// of the bad ones and optionally saving the raw bytes to a capture.
+pub struct FrameReader {
+    decoder: FrameDecoder,
+    buf: Vec<u8>,
+    stats: FrameStats,
+    // The first frame after connecting usually starts partway through.
+    synced: bool,
+    capture: Option<CaptureWriter>,
+}
+
+impl FrameReader {
+    pub fn new(decoder: FrameDecoder, capture: Option<CaptureWriter>) -> FrameReader {
+        FrameReader {
+            decoder: decoder,
+            buf: Vec::new(),
+            stats: FrameStats::default(),
+            synced: false,
+            capture: capture,
+        }
+    }
+
+    /// Forgets any partly read frame, for when the stream was interrupted.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.synced = false;
+    }
+
+    /// Reads until a good frame comes through. Returns `None` at the end of
+    /// the stream. Timeouts are passed along as errors, but whatever was read
+    /// before them is kept for the next call.
+    pub fn read_frame<R>(&mut self, port: &mut R) -> io::Result<Option<ControllerState>>
+        where R: BufRead,
+    {
+        loop {
+            let start = self.buf.len();
+            let result = port.read_until(self.decoder.terminator, &mut self.buf);
+
+            let capture_result = match self.capture.as_mut() {
+                Some(capture) => capture.write(&self.buf[start..]),
+                None => Ok(()),
+            };
+            if let Err(e) = capture_result {
+                warn!("Error writing serial capture, stopping capture: {}", e);
+                self.capture = None;
+            }
+
+            if result? == 0 {
+                return Ok(None);
+            }
+
+            if self.decoder.needs_more(&self.buf) {
+                continue;
+            }
+
+            let frame = mem::replace(&mut self.buf, Vec::new());
+            let result = self.decoder.decode(&frame);
+            if !self.synced {
+                self.synced = true;
+                if result.is_err() {
+                    continue;
+                }
+            }
+            self.stats.record(result.is_ok());
+            match result {
+                Ok(state) => return Ok(Some(state)),
+                Err(e) => debug!("Bad frame: {}", e),
+            }
+        }
+    }
+
+    pub fn add_metadata(&self, metadata: &mut Metadata) {
+        self.stats.add_metadata(metadata);
+    }
+
+    pub fn buttons(&self) -> &'static [Button] {
+        console_buttons(self.decoder.console)
+    }
+}
+
+/// Why a frame was thrown out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameError {
+    Length(usize, usize),
+    BadCharacter(u8),
+    ConstantBit(usize),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::Length(len, expected) => write!(f, "frame has {} bits, expected {}", len, expected),
+            FrameError::BadCharacter(byte) => write!(f, "unexpected byte 0x{:02x} in frame", byte),
+            FrameError::ConstantBit(idx) => write!(f, "constant bit {} has the wrong value", idx),
+        }
+    }
+}
+
+/// Turns one terminated frame from the serial port into a controller state.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameDecoder {
+    console: Console,
+    framing: SerialFraming,
+    terminator: u8,
+}
+
+impl FrameDecoder {
+    pub fn new(console: Console, framing: SerialFraming, terminator: u8) -> FrameDecoder {
+        FrameDecoder {
+            console: console,
+            framing: framing,
+            terminator: terminator,
+        }
+    }
+
+    pub fn from_conf(conf: &SerialConf) -> FrameDecoder {
+        Self::new(
+            conf.console.unwrap_or(Console::Gamecube),
+            conf.framing.unwrap_or(SerialFraming::Ascii),
+            conf.terminator.unwrap_or(DEFAULT_TERMINATOR))
+    }
+
+    pub fn console(&self) -> Console {
+        self.console
+    }
+
+    pub fn framing(&self) -> SerialFraming {
+        self.framing
+    }
+
+    pub fn terminator(&self) -> u8 {
+        self.terminator
+    }
+
+    /// Packed frames can contain the terminator as part of the data, so a
+    /// frame that ends early is really just the first part of one.
+    fn needs_more(&self, buf: &[u8]) -> bool {
+        match self.framing {
+            SerialFraming::Packed => {
+                buf.last() == Some(&self.terminator) && buf.len() <= frame_layout(self.console).report_bytes
+            }
+            _ => false,
+        }
+    }
+
+    fn bits(&self, frame: &[u8]) -> result::Result<Vec<bool>, FrameError> {
+        let layout = frame_layout(self.console);
+        let data = match frame.split_last() {
+            Some((&last, rest)) if last == self.terminator => rest,
+            _ => frame,
+        };
+
+        let bits = match self.framing {
+            SerialFraming::Ascii => {
+                let data = match data.split_last() {
+                    Some((&b'\r', rest)) => rest,
+                    _ => data,
+                };
+                data.iter()
+                    .map(|&byte| match byte {
+                        b'0' => Ok(false),
+                        b'1' => Ok(true),
+                        _ => Err(FrameError::BadCharacter(byte)),
+                    })
+                    .collect::<result::Result<Vec<bool>, FrameError>>()?
+            }
+            SerialFraming::Binary => {
+                data.iter()
+                    .map(|&byte| byte != 0)
+                    .collect()
+            }
+            SerialFraming::Packed => {
+                if data.len() != layout.report_bytes {
+                    return Err(FrameError::Length(data.len() * 8, layout.report_bytes * 8));
                 }
-                Ok(_) => {}
+                // Skip any bits of the report that aren't part of a frame.
+                data.iter()
+                    .flat_map(|&byte| (0..8).rev().map(move |bit| byte & (1 << bit) != 0))
+                    .skip(layout.report_bytes * 8 - layout.bits)
+                    .collect()
             }
-            let mut reader = StateReader::new(buf.chars());
-            if let Some(state) = reader.read_state() {
-                return Ok(state);
+        };
+
+        if bits.len() != layout.bits {
+            return Err(FrameError::Length(bits.len(), layout.bits));
+        }
+        for &(idx, value) in layout.constant_bits.iter() {
+            if bits[idx] != value {
+                return Err(FrameError::ConstantBit(idx));
             }
         }
+
+        Ok(bits)
+    }
+
+    /// Checks that a frame is well formed and reads the controller state out
+    /// of it.
+    pub fn decode(&self, frame: &[u8]) -> result::Result<ControllerState, FrameError> {
+        let bits = self.bits(frame)?;
+        let len = bits.len();
+        let mut reader = StateReader::new(bits.into_iter());
+        let state = match self.console {
+            Console::Gamecube => reader.read_gamecube(),
+            Console::N64 => reader.read_n64(),
+            Console::Snes => reader.read_snes(),
+            Console::Nes => reader.read_nes(),
+            Console::Classic => reader.read_classic(),
+        };
+        state.ok_or(FrameError::Length(len, frame_layout(self.console).bits))
     }
 }
 
@@ -58,7 +543,7 @@ struct StateReader<I> {
 }
 
 impl<I> StateReader<I>
-    where I: Iterator<Item = char>,
+    where I: Iterator<Item = bool>,
 {
     fn new(iter: I) -> StateReader<I> {
         StateReader {
@@ -67,30 +552,30 @@ impl<I> StateReader<I>
     }
 
     fn read_bool(&mut self) -> Option<bool> {
-        self.iter.next().map(|ch| ch == '1')
+        self.iter.next()
     }
 
     fn read_byte(&mut self) -> Option<u8> {
         let mut value = 0;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         value <<= 1;
-        value |= (self.iter.next()? == '1') as u8;
+        value |= self.iter.next()? as u8;
         Some(value)
     }
 
-    fn read_state(&mut self) -> Option<ControllerState> {
+    fn read_gamecube(&mut self) -> Option<ControllerState> {
         let mut state = ControllerState::default();
         self.iter.next()?;
         state.start = self.read_bool()?;
@@ -121,4 +606,226 @@ impl<I> StateReader<I>
 
         Some(state)
     }
+
+    /// Reads a signed byte and moves it so the center is at 128 like the
+    /// GameCube's sticks.
+    fn read_signed_axis(&mut self) -> Option<u8> {
+        Some((self.read_byte()? as i8 as i16 + 128) as u8)
+    }
+
+    fn read_n64(&mut self) -> Option<ControllerState> {
+        let mut state = ControllerState::default();
+        state.a = self.read_bool()?;
+        state.b = self.read_bool()?;
+        state.z = self.read_bool()?;
+        state.start = self.read_bool()?;
+        state.up = self.read_bool()?;
+        state.down = self.read_bool()?;
+        state.left = self.read_bool()?;
+        state.right = self.read_bool()?;
+        self.iter.next()?;
+        self.iter.next()?;
+        state.l_digital = self.read_bool()?;
+        state.r_digital = self.read_bool()?;
+        state.c_up = self.read_bool()?;
+        state.c_down = self.read_bool()?;
+        state.c_left = self.read_bool()?;
+        state.c_right = self.read_bool()?;
+
+        let analog_x = self.read_signed_axis()?;
+        let analog_y = self.read_signed_axis()?;
+        state.analog = (analog_x, analog_y);
+
+        Some(state)
+    }
+
+    fn read_snes(&mut self) -> Option<ControllerState> {
+        let mut state = ControllerState::default();
+        state.b = self.read_bool()?;
+        state.y = self.read_bool()?;
+        state.select = self.read_bool()?;
+        state.start = self.read_bool()?;
+        state.up = self.read_bool()?;
+        state.down = self.read_bool()?;
+        state.left = self.read_bool()?;
+        state.right = self.read_bool()?;
+        state.a = self.read_bool()?;
+        state.x = self.read_bool()?;
+        state.l_digital = self.read_bool()?;
+        state.r_digital = self.read_bool()?;
+
+        Some(state)
+    }
+
+    fn read_nes(&mut self) -> Option<ControllerState> {
+        let mut state = ControllerState::default();
+        state.a = self.read_bool()?;
+        state.b = self.read_bool()?;
+        state.select = self.read_bool()?;
+        state.start = self.read_bool()?;
+        state.up = self.read_bool()?;
+        state.down = self.read_bool()?;
+        state.left = self.read_bool()?;
+        state.right = self.read_bool()?;
+
+        Some(state)
+    }
+
+    /// Reads the 6-byte Classic Controller report. The sticks and triggers
+    /// have fewer bits than the GameCube's, so they get scaled up to match.
+    fn read_classic(&mut self) -> Option<ControllerState> {
+        let mut bytes = [0; 6];
+        for byte in bytes.iter_mut() {
+            *byte = self.read_byte()?;
+        }
+
+        let lx = bytes[0] & 0x3f;
+        let ly = bytes[1] & 0x3f;
+        let rx = ((bytes[0] & 0xc0) >> 3) | ((bytes[1] & 0xc0) >> 5) | ((bytes[2] & 0x80) >> 7);
+        let ry = bytes[2] & 0x1f;
+        let lt = ((bytes[2] & 0x60) >> 2) | ((bytes[3] & 0xe0) >> 5);
+        let rt = bytes[3] & 0x1f;
+
+        // Buttons are active low.
+        let pressed = |byte: u8, mask: u8| byte & mask == 0;
+
+        let mut state = ControllerState::default();
+        state.right = pressed(bytes[4], 0x80);
+        state.down = pressed(bytes[4], 0x40);
+        state.l_digital = pressed(bytes[4], 0x20);
+        state.select = pressed(bytes[4], 0x10);
+        state.home = pressed(bytes[4], 0x08);
+        state.start = pressed(bytes[4], 0x04);
+        state.r_digital = pressed(bytes[4], 0x02);
+        state.zl = pressed(bytes[5], 0x80);
+        state.b = pressed(bytes[5], 0x40);
+        state.y = pressed(bytes[5], 0x20);
+        state.a = pressed(bytes[5], 0x10);
+        state.x = pressed(bytes[5], 0x08);
+        state.zr = pressed(bytes[5], 0x04);
+        state.left = pressed(bytes[5], 0x02);
+        state.up = pressed(bytes[5], 0x01);
+        state.analog = (lx << 2, ly << 2);
+        state.c = (rx << 3, ry << 3);
+        state.l_analog = lt << 3;
+        state.r_analog = rt << 3;
+
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::metadata::Value;
+
+    const NEUTRAL: [u8; 6] = [128, 128, 128, 128, 0, 0];
+    // Start, Y, X, B, and A, then the always-set bit, then L, R, Z, and the
+    // D-pad.
+    const NOTHING_HELD: &'static str = "00000010000000";
+    const A_HELD: &'static str = "00000110000000";
+
+    /// An ASCII GameCube frame with the given button bits and axes.
+    fn gamecube_frame(buttons: &str, axes: [u8; 6]) -> Vec<u8> {
+        let mut frame = buttons.as_bytes().to_vec();
+        for &axis in axes.iter() {
+            frame.extend(format!("{:08b}", axis).bytes());
+        }
+        frame.push(b'\n');
+        frame
+    }
+
+    fn ascii_decoder() -> FrameDecoder {
+        FrameDecoder::new(Console::Gamecube, SerialFraming::Ascii, b'\n')
+    }
+
+    #[test]
+    fn decodes_gamecube_frames() {
+        let frame = gamecube_frame(A_HELD, [200, 60, 30, 220, 255, 100]);
+        let state = ascii_decoder().decode(&frame).unwrap();
+        assert!(state.a);
+        assert!(!state.b && !state.start && !state.z && !state.l_digital);
+        assert_eq!(state.analog, (200, 60));
+        assert_eq!(state.c, (30, 220));
+        assert_eq!((state.l_analog, state.r_analog), (255, 100));
+    }
+
+    #[test]
+    fn accepts_carriage_returns() {
+        let mut frame = gamecube_frame(A_HELD, NEUTRAL);
+        frame.insert(frame.len() - 1, b'\r');
+        assert!(ascii_decoder().decode(&frame).unwrap().a);
+    }
+
+    #[test]
+    fn rejects_frames_of_the_wrong_length() {
+        let mut frame = gamecube_frame(A_HELD, NEUTRAL);
+        frame.remove(0);
+        assert_eq!(ascii_decoder().decode(&frame).unwrap_err(), FrameError::Length(61, 62));
+
+        let mut frame = gamecube_frame(A_HELD, NEUTRAL);
+        frame.insert(0, b'0');
+        assert_eq!(ascii_decoder().decode(&frame).unwrap_err(), FrameError::Length(63, 62));
+    }
+
+    #[test]
+    fn rejects_bad_characters() {
+        let mut frame = gamecube_frame(A_HELD, NEUTRAL);
+        frame[20] = b'2';
+        assert_eq!(ascii_decoder().decode(&frame).unwrap_err(), FrameError::BadCharacter(b'2'));
+    }
+
+    #[test]
+    fn rejects_cleared_always_set_bit() {
+        let frame = gamecube_frame("00000100000000", NEUTRAL);
+        assert_eq!(ascii_decoder().decode(&frame).unwrap_err(), FrameError::ConstantBit(6));
+    }
+
+    #[test]
+    fn decodes_binary_and_packed_frames() {
+        let ascii = gamecube_frame(A_HELD, [200, 60, 30, 220, 255, 100]);
+        let bits: Vec<u8> = ascii[..62].iter().map(|&c| c - b'0').collect();
+
+        let mut binary = bits.clone();
+        binary.push(b'\n');
+        let decoder = FrameDecoder::new(Console::Gamecube, SerialFraming::Binary, b'\n');
+        let state = decoder.decode(&binary).unwrap();
+        assert!(state.a);
+        assert_eq!(state.analog, (200, 60));
+
+        // Packed reports are padded at the front out to whole bytes.
+        let mut packed: Vec<u8> = [0, 0].iter().chain(bits.iter())
+            .collect::<Vec<_>>()
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0, |byte, &&bit| byte << 1 | bit))
+            .collect();
+        packed.push(b'\n');
+        let decoder = FrameDecoder::new(Console::Gamecube, SerialFraming::Packed, b'\n');
+        let state = decoder.decode(&packed).unwrap();
+        assert!(state.a);
+        assert_eq!(state.c, (30, 220));
+    }
+
+    #[test]
+    fn frame_reader_skips_partial_first_frame_and_counts_bad_ones() {
+        let mut stream = b"0110\n".to_vec();
+        stream.extend(gamecube_frame(A_HELD, NEUTRAL));
+        stream.extend(gamecube_frame("00000100000000", NEUTRAL));
+        stream.extend(gamecube_frame(NOTHING_HELD, [0, 255, 128, 128, 0, 0]));
+        let mut port = Cursor::new(stream);
+        let mut reader = FrameReader::new(ascii_decoder(), None);
+
+        assert!(reader.read_frame(&mut port).unwrap().unwrap().a);
+        let state = reader.read_frame(&mut port).unwrap().unwrap();
+        assert!(!state.a);
+        assert_eq!(state.analog, (0, 255));
+        assert!(reader.read_frame(&mut port).unwrap().is_none());
+
+        let mut metadata = Metadata::new();
+        reader.add_metadata(&mut metadata);
+        assert_eq!(metadata["serial.good_frames"], Value::Int(2));
+        assert_eq!(metadata["serial.bad_frames"], Value::Int(1));
+    }
 }
@@ -1,49 +1,156 @@
+use std::f32::consts::PI;
 use std::thread;
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
 use std::path::PathBuf;
 
-use sdl2::render::Canvas;
-use sdl2::event::Event;
-use sdl2::video::Window;
-use sdl2::pixels::Color;
+use log::{error, info, warn};
+use sdl2::image::SaveSurface;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::video::{Window, WindowContext};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Rect, Point};
+use sdl2::surface::Surface;
 use sdl2::Sdl;
 
-use crate::texture_cache::{CanvasExt, TextureCache, Image, Analog, Trigger};
-use crate::controller_state::ControllerState;
-use crate::configuration::{ThemeConfiguration, TriggerDirection};
-use crate::error::{Error, Result};
+use gc_input::cancel::CancelToken;
+use gc_input::input_reader::ReaderContext;
+use gc_input::input_reader::keyboard_reader::KeyboardInput;
+use gc_input::controller_state::StickPosition;
+use gc_input::error::{Error, Result};
+
+use crate::bitmap_font;
+use crate::heatmap::{Heatmap, Heatmaps, HEATMAP_SIZE};
+use crate::pad_bridge::PadBridge;
+use crate::text_format::Template;
+use crate::texture_cache::{CanvasExt, TextureCache, TextureCacheCreator, Image, Analog, Trigger, Stick, HeatmapImage, Text};
+use crate::shared_state::{SharedState, Trail};
+use crate::theme::{ThemeConfiguration, TriggerDirection};
+use crate::viewer_configuration::{WindowConf, WindowState, ScaleFilter};
+
+const WINDOW_STATE_PATH: &'static str = "window_state.yaml";
+const HEATMAP_ANALOG_PATH: &'static str = "heatmap_analog.png";
+const HEATMAP_C_PATH: &'static str = "heatmap_c.png";
+const DEBUG_OVERLAY_FORMAT: &'static str = "POLL {poll.hz:6.1} HZ
+JITTER {poll.jitter_ms:6.2} MS
+GAP {poll.recent_max_gap_ms:6.1} MS
+MAX GAP {poll.max_gap_ms:6.1} MS";
 
 pub struct InputWindow {
     sdl: Sdl,
     canvas: Canvas<Window>,
-    state: Arc<Mutex<ControllerState>>,
+    state: Arc<Mutex<SharedState>>,
+    heatmaps: Arc<Mutex<Heatmaps>>,
+    size: (u32, u32),
+    debug_overlay: Option<Template>,
+    keyboard: Arc<Mutex<KeyboardInput>>,
+    /// Only there when reading from the sdl input method.
+    pads: Option<PadBridge>,
+    /// Where the mouse was pressed for dragging each stick.
+    drag_origins: (Option<(i32, i32)>, Option<(i32, i32)>),
+    cancel: CancelToken,
 }
 
 impl InputWindow {
-    pub fn new(conf: &ThemeConfiguration, state: Arc<Mutex<ControllerState>>) -> Result<InputWindow> {
+    pub fn new(conf: &ThemeConfiguration, window_conf: &WindowConf, state: Arc<Mutex<SharedState>>, heatmaps: Arc<Mutex<Heatmaps>>, context: ReaderContext, read_pads: bool) -> Result<InputWindow> {
         let sdl = sdl2::init()?;
         let video = sdl.video()?;
 
-        let window = video.window("GC Input Viewer", conf.size.0, conf.size.1)
-            .position_centered()
-            .build()
+        let filter = window_conf.filter.unwrap_or(ScaleFilter::Nearest);
+        // Has to be set before any textures are made.
+        let quality = match filter {
+            ScaleFilter::Linear => "linear",
+            ScaleFilter::Nearest | ScaleFilter::Integer => "nearest",
+        };
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", quality);
+
+        let scale = window_conf.scale.unwrap_or(1.0);
+        let width = (conf.size.0 as f32 * scale) as u32;
+        let height = (conf.size.1 as f32 * scale) as u32;
+        let title = window_conf.title.as_ref().map(|t| t.as_str()).unwrap_or("GC Input Viewer");
+        let mut builder = video.window(title, width, height);
+        builder.resizable();
+
+        // A position in the config always wins over the one from last time.
+        let position = window_conf.position
+            .or_else(|| WindowState::from_path(WINDOW_STATE_PATH).ok().map(|s| s.position));
+        match position {
+            Some((x, y)) => builder.position(x, y),
+            None => builder.position_centered(),
+        };
+        if window_conf.borderless.unwrap_or(false) {
+            builder.borderless();
+        }
+        if window_conf.always_on_top.unwrap_or(false) {
+            let flags = builder.window_flags() | sdl2::sys::SDL_WindowFlags::SDL_WINDOW_ALWAYS_ON_TOP as u32;
+            builder.set_window_flags(flags);
+        }
+
+        let window = builder.build()
             .map_err(|e| Error::Sdl2Error(e.into()))?;
 
-        let canvas = window.into_canvas()
+        let mut canvas = window.into_canvas()
             .accelerated()
             .target_texture()
             .build()
             .map_err(|e| Error::Sdl2Error(e.into()))?;
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        // Draw at the theme's size and let SDL scale it to the window.
+        canvas.set_logical_size(conf.size.0, conf.size.1)
+            .map_err(|e| Error::Sdl2Error(e.into()))?;
+        if filter == ScaleFilter::Integer {
+            unsafe {
+                sdl2::sys::SDL_RenderSetIntegerScale(canvas.raw(), sdl2::sys::SDL_bool::SDL_TRUE);
+            }
+        }
+
+        let debug_overlay = if window_conf.debug_overlay.unwrap_or(false) {
+            Some(Template::parse(DEBUG_OVERLAY_FORMAT)?)
+        }
+        else {
+            None
+        };
+
+        // Pads are opened here, since SDL events only come to this thread.
+        // The window still works without them.
+        let pads = if read_pads {
+            match PadBridge::new(&sdl, context.pads) {
+                Ok(pads) => Some(pads),
+                Err(e) => {
+                    error!("Could not read pads: {}", e);
+                    None
+                }
+            }
+        }
+        else {
+            None
+        };
 
         Ok(InputWindow{
             sdl: sdl,
             canvas: canvas,
             state: state,
+            heatmaps: heatmaps,
+            size: conf.size,
+            debug_overlay: debug_overlay,
+            keyboard: context.keyboard,
+            pads: pads,
+            drag_origins: (None, None),
+            cancel: context.cancel,
         })
     }
 
+    fn snap_to_scale(&mut self, scale: u32) -> Result<()> {
+        self.canvas.window_mut()
+            .set_size(self.size.0 * scale, self.size.1 * scale)
+            .map_err(|e| Error::Sdl2Error(e.into()))?;
+        Ok(())
+    }
+
     fn draw_image(&mut self, image: &Image) -> Result<()> {
         self.canvas.copy(&image.tex, None, image.dst)?;
         Ok(())
@@ -64,6 +171,72 @@ impl InputWindow {
         Ok(())
     }
 
+    fn draw_stick(&mut self, stick: &Stick, position: (u8, u8), trail: &Trail) -> Result<()> {
+        let (cx, cy) = stick.center;
+        let radius = stick.radius as f32;
+        let to_point = |x: f32, y: f32| Point::new(cx + (x * radius) as i32, cy - (y * radius) as i32);
+        let color = stick.color;
+
+        self.canvas.set_draw_color(color);
+
+        // Gate outline, going through each notch at full deflection.
+        if !stick.notches.is_empty() {
+            let mut gate: Vec<Point> = stick.notches
+                .iter()
+                .map(|angle| {
+                    let angle = angle.to_radians();
+                    to_point(angle.cos(), angle.sin())
+                })
+                .collect();
+            gate.push(gate[0]);
+            self.canvas.draw_lines(&gate[..])?;
+        }
+
+        if stick.deadzone > 0.0 {
+            let deadzone: Vec<Point> = (0..33)
+                .map(|idx| {
+                    let angle = idx as f32 * 2.0 * PI / 32.0;
+                    to_point(stick.deadzone * angle.cos(), stick.deadzone * angle.sin())
+                })
+                .collect();
+            self.canvas.draw_lines(&deadzone[..])?;
+        }
+
+        // Trail of recent positions, oldest first so newer ones draw on top.
+        let trail_len = trail.positions.len();
+        for (idx, old_position) in trail.positions.iter().enumerate() {
+            let alpha = ((idx + 1) * 255 / trail_len) as u8;
+            let old = StickPosition::from_raw(*old_position);
+            let point = to_point(old.x, old.y);
+            self.canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, alpha / 2));
+            self.canvas.fill_rect(Rect::new(point.x() - 1, point.y() - 1, 3, 3))?;
+        }
+
+        let raw = StickPosition::from_raw(position);
+        let point = to_point(raw.x, raw.y);
+        self.canvas.set_draw_color(color);
+        self.canvas.draw_line(Point::new(cx, cy), point)?;
+        self.canvas.fill_rect(Rect::new(point.x() - 2, point.y() - 2, 5, 5))?;
+
+        if let Some(readout) = stick.readout {
+            let game = StickPosition::from_raw_with_deadzone(position, stick.deadzone);
+            let text = format!("X {:3} {:+.2}\nY {:3} {:+.2}\nA {:5.1} M {:.2}",
+                position.0, game.x,
+                position.1, game.y,
+                game.angle(), game.magnitude());
+            bitmap_font::draw_text(&mut self.canvas, &text, readout, stick.readout_scale, color)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_heatmap(&mut self, image: &mut HeatmapImage, pixels: &[u8]) -> Result<()> {
+        image.tex.update(None, pixels, HEATMAP_SIZE as usize * 4)
+            .map_err(|e| Error::Sdl2Error(e.into()))?;
+        self.canvas.copy(&image.tex, None, image.dst)?;
+        Ok(())
+    }
+
     fn draw_trigger(&mut self, trigger: &Trigger, value: u8) -> Result<()> {
         let tex_info = trigger.image.tex.query();
 
@@ -117,7 +290,77 @@ impl InputWindow {
         Ok(())
     }
 
-    fn update(&mut self, textures: &mut TextureCache, state: ControllerState) -> Result<()> {
+    fn draw_text<'a>(&mut self, creator: &'a TextureCacheCreator<WindowContext>, text: &mut Text<'a>, shared: &SharedState) -> Result<()> {
+        let rendered = text.template.render(|name| {
+            shared.controller.value(name).or_else(|| shared.metadata.get(name).cloned())
+        });
+
+        if text.font.is_none() {
+            bitmap_font::draw_text(&mut self.canvas, &rendered, text.dst, text.scale, text.color)?;
+            return Ok(());
+        }
+
+        creator.render_text(text, rendered)?;
+        if let Some((_, ref lines)) = text.rendered {
+            for &(ref tex, dst) in lines.iter() {
+                self.canvas.copy(tex, None, dst)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws how fast input is arriving in the top left corner.
+    fn draw_debug_overlay(&mut self, shared: &SharedState) -> Result<()> {
+        let rendered = match self.debug_overlay {
+            Some(ref template) => template.render(|name| shared.metadata.get(name).cloned()),
+            None => return Ok(()),
+        };
+        let scale = 1;
+        let (text_w, text_h) = bitmap_font::text_size(&rendered, scale);
+
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        self.canvas.fill_rect(Rect::new(0, 0, text_w + 4, text_h + 4))?;
+        bitmap_font::draw_text(&mut self.canvas, &rendered, (2, 2), scale, Color::RGB(0, 255, 0))?;
+        Ok(())
+    }
+
+    /// Dims the whole window and writes `message` across the middle of it.
+    fn draw_banner(&mut self, message: &str) -> Result<()> {
+        let scale = 3;
+        let (text_w, text_h) = bitmap_font::text_size(message, scale);
+        let x = (self.size.0 as i32 - text_w as i32) / 2;
+        let y = (self.size.1 as i32 - text_h as i32) / 2;
+
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas.fill_rect(Rect::new(0, 0, self.size.0, self.size.1))?;
+        bitmap_font::draw_text(&mut self.canvas, message, (x, y), scale, Color::RGB(255, 255, 255))?;
+        Ok(())
+    }
+
+    /// Writes `message` in a strip along the bottom of the window, wrapping it
+    /// to fit.
+    fn draw_status(&mut self, message: &str) -> Result<()> {
+        let scale = 1;
+        let max_chars = ((self.size.0.saturating_sub(4) / (4 * scale)) as usize).max(1);
+        let chars: Vec<char> = message.chars()
+            .map(|ch| if bitmap_font::has_glyph(ch) { ch } else { '?' })
+            .collect();
+        let wrapped = chars.chunks(max_chars)
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (_, text_h) = bitmap_font::text_size(&wrapped, scale);
+        let y = self.size.1 as i32 - text_h as i32 - 4;
+
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        self.canvas.fill_rect(Rect::new(0, y, self.size.0, text_h + 4))?;
+        bitmap_font::draw_text(&mut self.canvas, &wrapped, (2, y + 2), scale, Color::RGB(255, 96, 96))?;
+        Ok(())
+    }
+
+    fn update<'a>(&mut self, creator: &'a TextureCacheCreator<WindowContext>, textures: &mut TextureCache<'a>, shared: &SharedState) -> Result<()> {
+        let state = shared.controller;
+
         self.canvas.set_draw_color(Color::RGB(255, 255, 255));
         self.canvas.clear();
 
@@ -194,6 +437,54 @@ impl InputWindow {
                 .map(|i| self.draw_image(i))
                 .unwrap_or(Ok(()))?;
         }
+        if state.select {
+            textures.select
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.home {
+            textures.home
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.zl {
+            textures.zl
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.zr {
+            textures.zr
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.c_up {
+            textures.c_up
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.c_down {
+            textures.c_down
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.c_left {
+            textures.c_left
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
+        if state.c_right {
+            textures.c_right
+                .as_ref()
+                .map(|i| self.draw_image(i))
+                .unwrap_or(Ok(()))?;
+        }
 
         textures.analog
             .as_ref()
@@ -204,6 +495,32 @@ impl InputWindow {
             .map(|i| self.draw_analog(i, state.c))
             .unwrap_or(Ok(()))?;
 
+        // Only hold the lock long enough to grab the pixels.
+        let (analog_pixels, c_pixels) = {
+            let heatmaps = self.heatmaps.lock().unwrap();
+            (textures.analog_heatmap.as_ref().map(|h| heatmaps.analog.pixels(h.color.rgb())),
+             textures.c_heatmap.as_ref().map(|h| heatmaps.c.pixels(h.color.rgb())))
+        };
+        if let (Some(image), Some(pixels)) = (textures.analog_heatmap.as_mut(), analog_pixels) {
+            self.draw_heatmap(image, &pixels)?;
+        }
+        if let (Some(image), Some(pixels)) = (textures.c_heatmap.as_mut(), c_pixels) {
+            self.draw_heatmap(image, &pixels)?;
+        }
+
+        textures.analog_stick
+            .as_ref()
+            .map(|s| self.draw_stick(s, state.analog, &shared.analog_trail))
+            .unwrap_or(Ok(()))?;
+        textures.c_stick
+            .as_ref()
+            .map(|s| self.draw_stick(s, state.c, &shared.c_trail))
+            .unwrap_or(Ok(()))?;
+
+        for text in textures.text.iter_mut() {
+            self.draw_text(creator, text, shared)?;
+        }
+
         textures.l_analog
             .as_ref()
             .map(|i| self.draw_trigger(i, state.l_analog))
@@ -213,31 +530,165 @@ impl InputWindow {
             .map(|i| self.draw_trigger(i, state.r_analog))
             .unwrap_or(Ok(()))?;
 
+        // Overlays go on top of everything in the theme.
+        self.draw_debug_overlay(shared)?;
+
+        if shared.disconnected {
+            self.draw_banner("DISCONNECTED")?;
+        }
+        if let Some(ref status) = shared.status {
+            self.draw_status(status)?;
+        }
+
         self.canvas.present();
         Ok(())
     }
 
+    /// Saves both heatmaps as PNGs and prints what they show.
+    fn export_heatmaps(&self, textures: &TextureCache) -> Result<()> {
+        let heatmaps = self.heatmaps.lock().unwrap().clone();
+        let default_color = Color::RGB(255, 0, 0);
+        let analog_color = textures.analog_heatmap.as_ref().map(|h| h.color).unwrap_or(default_color);
+        let c_color = textures.c_heatmap.as_ref().map(|h| h.color).unwrap_or(default_color);
+
+        save_heatmap(&heatmaps.analog, HEATMAP_ANALOG_PATH, analog_color)?;
+        save_heatmap(&heatmaps.c, HEATMAP_C_PATH, c_color)?;
+        info!("Saved heatmaps to {} and {}", HEATMAP_ANALOG_PATH, HEATMAP_C_PATH);
+        info!("Analog stick:\n{}", heatmaps.analog.report());
+        info!("C stick:\n{}", heatmaps.c.report());
+        Ok(())
+    }
+
+    /// Passes keys and mouse drags on to the keyboard input method. Dragging
+    /// with the left mouse button moves the analog stick, and with the right
+    /// button moves the C stick.
+    fn handle_keyboard_input(&mut self, event: &Event) {
+        // Dragging a quarter of the way across the theme is a full tilt.
+        let range = self.size.0.min(self.size.1) as f32 / 4.0;
+        let mut keyboard = self.keyboard.lock().unwrap();
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), repeat: false, .. } =>
+                keyboard.press(&keycode.name()),
+            Event::KeyUp { keycode: Some(keycode), .. } =>
+                keyboard.release(&keycode.name()),
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                self.drag_origins.0 = Some((x, y));
+                keyboard.analog_drag = Some((0.0, 0.0));
+            }
+            Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => {
+                self.drag_origins.1 = Some((x, y));
+                keyboard.c_drag = Some((0.0, 0.0));
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                self.drag_origins.0 = None;
+                keyboard.analog_drag = None;
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Right, .. } => {
+                self.drag_origins.1 = None;
+                keyboard.c_drag = None;
+            }
+            Event::MouseMotion { x, y, .. } => {
+                if let Some(origin) = self.drag_origins.0 {
+                    keyboard.analog_drag = Some(drag_offset(origin, (x, y), range));
+                }
+                if let Some(origin) = self.drag_origins.1 {
+                    keyboard.c_drag = Some(drag_offset(origin, (x, y), range));
+                }
+            }
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.drag_origins = (None, None);
+                keyboard.clear();
+            }
+            _ => {}
+        }
+    }
+
     pub fn run(&mut self, base: PathBuf, conf: ThemeConfiguration) -> Result<()> {
-        let tex_cache_creator = self.canvas.texture_cache_creator(base);
+        let tex_cache_creator = self.canvas.texture_cache_creator(base)?;
         let mut tex = tex_cache_creator.texture_cache(&conf)?;
 
         let mut event_pump = self.sdl.event_pump()?;
-        'running: loop {
+        // Drawing errors are reported once and then drawing carries on.
+        let mut last_error = None;
+        // Runs until the window is closed or something else wants to quit.
+        'running: while !self.cancel.is_cancelled() {
 
             for event in event_pump.poll_iter() {
+                self.handle_keyboard_input(&event);
+                if let Some(ref mut pads) = self.pads {
+                    pads.handle_event(&event);
+                }
                 match event {
                     Event::Quit {..} =>
                         break 'running,
+                    // Keys the keyboard input method uses aren't hotkeys.
+                    Event::KeyDown { keycode: Some(keycode), .. } if self.keyboard.lock().unwrap().is_bound(&keycode.name()) => {}
+                    Event::KeyDown { keycode: Some(Keycode::Num1), .. } =>
+                        report_error(&mut last_error, self.snap_to_scale(1)),
+                    Event::KeyDown { keycode: Some(Keycode::Num2), .. } =>
+                        report_error(&mut last_error, self.snap_to_scale(2)),
+                    Event::KeyDown { keycode: Some(Keycode::Num3), .. } =>
+                        report_error(&mut last_error, self.snap_to_scale(3)),
+                    Event::KeyDown { keycode: Some(Keycode::H), .. } => {
+                        if let Err(e) = self.export_heatmaps(&tex) {
+                            error!("Could not save heatmaps: {}", e);
+                        }
+                    }
                     _ => {}
                 }
             }
 
-            let state = *self.state.lock().unwrap();
-            self.update(&mut tex, state)?;
+            if let Some(ref mut pads) = self.pads {
+                pads.update();
+            }
+            let shared = self.state.lock().unwrap().clone();
+            let result = self.update(&tex_cache_creator, &mut tex, &shared);
+            report_error(&mut last_error, result);
 
             thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
         }
 
+        let window_state = WindowState {
+            position: self.canvas.window().position(),
+        };
+        if let Err(e) = window_state.save(WINDOW_STATE_PATH) {
+            warn!("Could not save window position: {}", e);
+        }
+
         Ok(())
     }
 }
+
+/// How far the mouse has been dragged from `origin`, from -1 to 1 with up
+/// being positive, kept within the stick's circle.
+fn drag_offset(origin: (i32, i32), position: (i32, i32), range: f32) -> (f32, f32) {
+    let x = (position.0 - origin.0) as f32 / range;
+    let y = (origin.1 - position.1) as f32 / range;
+    let magnitude = x.hypot(y);
+    if magnitude > 1.0 {
+        (x / magnitude, y / magnitude)
+    }
+    else {
+        (x, y)
+    }
+}
+
+/// Prints the error in `result` unless it's the same as the last one.
+fn report_error(last_error: &mut Option<String>, result: Result<()>) {
+    if let Err(e) = result {
+        let message = e.to_string();
+        if last_error.as_ref() != Some(&message) {
+            error!("Error in display thread: {}", message);
+            *last_error = Some(message);
+        }
+    }
+}
+
+/// Saves `heatmap` to `path` as a PNG, drawn in `color`.
+fn save_heatmap(heatmap: &Heatmap, path: &str, color: Color) -> Result<()> {
+    let mut pixels = heatmap.pixels(color.rgb());
+    let surface = Surface::from_data(&mut pixels, HEATMAP_SIZE, HEATMAP_SIZE, HEATMAP_SIZE * 4, PixelFormatEnum::RGBA8888)
+        .map_err(|e| Error::Sdl2Error(e.into()))?;
+    surface.save(path)
+        .map_err(|e| Error::Sdl2Error(e.into()))
+}
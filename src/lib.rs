@@ -0,0 +1,24 @@
+//! Reading GameCube (and other) controller input from recordings, games, and
+//! NintendoSpy-style serial devices.
+//!
+//! Every source implements [`InputReader`](input_reader/trait.InputReader.html),
+//! which hands back a [`ControllerState`](controller_state/struct.ControllerState.html)
+//! each time it's asked. The easiest way to get one is from a `conf.yaml` style
+//! [`InputSource`](configuration/enum.InputSource.html) with
+//! [`input_reader::from_source`](input_reader/fn.from_source.html).
+
+pub mod cancel;
+pub mod configuration;
+pub mod controller_state;
+pub mod error;
+pub mod health_check;
+pub mod input_reader;
+pub mod metadata;
+pub mod output_sink;
+pub mod stats;
+pub mod transform;
+
+pub use crate::configuration::InputSource;
+pub use crate::controller_state::{Button, ControllerState};
+pub use crate::error::{Error, Result};
+pub use crate::input_reader::{from_source, InputReader, ReaderContext};
@@ -0,0 +1,178 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use gc_input::error::{Error, Result};
+
+use crate::viewer_configuration::LogConf;
+
+const DEFAULT_MAX_KB: u64 = 1024;
+const DEFAULT_KEEP: u32 = 3;
+
+/// A log file that moves itself out of the way once it gets too big, keeping
+/// the last few as `<path>.1`, `<path>.2`, and so on.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, keep: u32) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_owned(),
+            max_bytes: max_bytes,
+            keep: keep,
+            file: file,
+            size: size,
+        })
+    }
+
+    fn rotated_path(&self, idx: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", idx));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for idx in (1..self.keep).rev() {
+            let from = self.rotated_path(idx);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(idx + 1))?;
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+struct Logger {
+    level: LevelFilter,
+    stderr: bool,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!("{}.{:03} {:<5} {}: {}\n",
+            time.as_secs(),
+            time.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args());
+
+        if self.stderr {
+            let _ = io::stderr().write_all(line.as_bytes());
+        }
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.write_line(&line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+/// Pulls the log level out of command line arguments, returning it along
+/// with the rest of the arguments. `-v` and `-vv` turn on debug and trace
+/// messages, and `--log-level=<level>` or `--log-level <level>` picks any
+/// level.
+pub fn level_from_args<I>(args: I) -> Result<(Option<LevelFilter>, Vec<String>)>
+    where I: IntoIterator<Item = String>,
+{
+    let mut level = None;
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-v" {
+            level = Some(LevelFilter::Debug);
+        }
+        else if arg == "-vv" {
+            level = Some(LevelFilter::Trace);
+        }
+        else if arg == "--log-level" {
+            let value = args.next()
+                .ok_or_else(|| Error::LogError("--log-level needs a level".to_string()))?;
+            level = Some(parse_level(&value)?);
+        }
+        else if arg.starts_with("--log-level=") {
+            level = Some(parse_level(&arg["--log-level=".len()..])?);
+        }
+        else {
+            rest.push(arg);
+        }
+    }
+    Ok((level, rest))
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    LevelFilter::from_str(level)
+        .map_err(|_| Error::LogError(format!("unknown log level \"{}\"", level)))
+}
+
+/// Sets up logging from the config file. A level given on the command line
+/// wins over the one in `conf`.
+pub fn init(conf: &LogConf, cli_level: Option<LevelFilter>) -> Result<()> {
+    let level = match (cli_level, conf.level.as_ref()) {
+        (Some(level), _) => level,
+        (None, Some(level)) => parse_level(level)?,
+        (None, None) => LevelFilter::Info,
+    };
+    let file = match conf.file.as_ref() {
+        Some(path) => {
+            let max_bytes = conf.max_kb.unwrap_or(DEFAULT_MAX_KB) * 1024;
+            let keep = conf.keep.unwrap_or(DEFAULT_KEEP);
+            Some(Mutex::new(RotatingFile::open(path, max_bytes, keep)?))
+        }
+        None => None,
+    };
+
+    let logger = Logger {
+        level: level,
+        stderr: conf.stderr.unwrap_or(true),
+        file: file,
+    };
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| Error::LogError(e.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
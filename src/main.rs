@@ -1,106 +1,345 @@
 mod input_window;
-mod configuration;
 mod texture_cache;
-mod controller_state;
-mod input_reader;
-mod error;
+mod bitmap_font;
+mod shared_state;
+mod logger;
+mod pad_bridge;
+mod heatmap;
+mod poll_stats;
+mod retry;
+mod text_format;
+mod theme;
+mod viewer_configuration;
 
-use std::error::Error;
+use std::env;
+use std::error;
 use std::process;
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
 
+use log::{error, info, warn, LevelFilter};
+use sdl2::keyboard::Keycode;
+use serialport::SerialPortType;
+
+use gc_input::{ControllerState, Error, InputReader};
+use gc_input::cancel::CancelToken;
+use gc_input::configuration::{InputSource, OutputConf};
+use gc_input::health_check;
+use gc_input::input_reader::{self, ReaderContext};
+use gc_input::input_reader::keyboard_reader::KeyboardInput;
+use gc_input::input_reader::sdl_reader::SdlPads;
+use gc_input::metadata::Metadata;
+use gc_input::output_sink::{self, OutputSink};
+use gc_input::output_sink::stats_sink::StatsSink;
+use gc_input::stats::SessionStats;
+use gc_input::transform::Transform;
+
+use crate::heatmap::Heatmaps;
 use crate::input_window::InputWindow;
-use crate::configuration::{Configuration, InputSource};
-use crate::controller_state::ControllerState;
-use crate::input_reader::InputReader;
-use crate::input_reader::dtm_reader::DtmReader;
-use crate::input_reader::serial_reader::SerialReader;
-#[cfg(windows)]
-use crate::input_reader::sa2_reader::Sa2Reader;
+use crate::pad_bridge::PadBridge;
+use crate::poll_stats::PollStats;
+use crate::retry::{Backoff, RetryPolicy};
+use crate::shared_state::{SharedState, Trail};
+use crate::theme::StickConf;
+use crate::viewer_configuration::Configuration;
 
 fn main() {
     // Print out version info.
     println!("GC Input Viewer by OnVar.");
     println!("Version {}", env!("CARGO_PKG_VERSION"));
 
+    let (cli_level, args) = logger::level_from_args(env::args().skip(1)).unwrap_or_barf("Bad command line");
+    let command = args.into_iter().find(|arg| !arg.starts_with('-'));
+    if command.as_ref().map(|arg| arg.as_str()) == Some("list-ports") {
+        list_ports();
+        return;
+    }
+
     // Read from configuration file.
-    let conf = Configuration::from_path("conf.yaml").unwrap_or_barf("Error opening \"conf.yaml\" configuration file");
-    let theme = conf.theme;
-    let base = conf.theme_path;
+    let conf = Configuration::from_path("conf.yaml").unwrap_or_barf("Could not load configuration");
+    logger::init(&conf.log, cli_level).unwrap_or_barf("Could not set up logging");
+    if let Some(ref theme_file) = conf.theme_file {
+        info!("Using theme {}", theme_file.display());
+    }
+
+    if command.as_ref().map(|arg| arg.as_str()) == Some("health-check") {
+        if let InputSource::Keyboard(_) = conf.input {
+            barf("The keyboard input method needs the window, so it can't be used for a health check");
+        }
+        let context = ReaderContext::new();
+        if let InputSource::Sdl(_) = conf.input {
+            spawn_pad_thread(&context);
+        }
+        let mut reader = input_reader::from_source(conf.input, &context).unwrap_or_barf("Could not open input");
+        println!();
+        println!("Controller health check");
+        let report = health_check::run(&mut *reader, |line| println!("{}", line)).unwrap_or_barf("Error reading input");
+        println!();
+        println!("Results");
+        println!();
+        print!("{}", report);
+        context.cancel.cancel();
+        return;
+    }
+
+    let window_conf = conf.window;
+    let poll_log_interval = conf.poll_log_secs.map(Duration::from_secs);
+    let transform = Transform::from_conf(&conf.transform).unwrap_or_barf("Error in transform settings");
+    // Keep track of presses and such for text elements and stats outputs.
+    let stats_mutex = Arc::new(Mutex::new(SessionStats::new()));
+    // Everywhere the input goes besides the window, along with the last
+    // error from each. Stats outputs save the stats kept here rather than
+    // keeping their own.
+    let mut outputs: Vec<Box<dyn OutputSink>> = conf.outputs.into_iter()
+        .map(|output| match output {
+            OutputConf::Stats(path) => Box::new(StatsSink::saving(path, stats_mutex.clone())) as Box<dyn OutputSink>,
+            output => output_sink::from_conf(output).unwrap_or_barf("Could not set up output"),
+        })
+        .collect();
+    let mut output_errors = vec![None; outputs.len()];
 
     // Take input from whatever input method is specified in the config file.
-    let mut reader = get_input(conf.input);
+    // It gets opened in the input-reader loop, so it can be retried if it
+    // isn't available yet.
+    let input = conf.input;
+    let mut reader = None;
+    let mut backoff = Backoff::new(RetryPolicy::for_source(&input).with_conf(&conf.retry));
 
     // Make a controller state to share across threads.
-    let state_mutex = Arc::new(Mutex::new(ControllerState::default()));
-    // And a channel to tell us when the display thread has exited.
-    let (done_sender, done_receiver) = mpsc::channel::<()>();
+    // The sticks' trails are kept here too, as long as the theme wants them.
+    let trail = |stick: Option<&StickConf>| Trail::new(stick.and_then(|s| s.trail).unwrap_or(0));
+    let state_mutex = Arc::new(Mutex::new(SharedState {
+        analog_trail: trail(conf.theme.as_ref().and_then(|t| t.analog_stick.as_ref())),
+        c_trail: trail(conf.theme.as_ref().and_then(|t| t.c_stick.as_ref())),
+        ..SharedState::default()
+    }));
+    // Keep track of how fast the input is coming in.
+    let mut poll_stats = PollStats::new();
+    let mut last_poll_log = Instant::now();
+    // And where the sticks have been, shared with the display thread so it
+    // can draw and save them.
+    let heatmaps_mutex = Arc::new(Mutex::new(Heatmaps::new()));
+    // And a token to tell everything to stop, whether the window was closed
+    // or we were asked to quit.
+    let cancel = CancelToken::new();
+    // Readers get opened with this, and the display thread feeds the
+    // keyboard input method through it.
+    let context = ReaderContext {
+        cancel: cancel.clone(),
+        // Only take key names SDL knows, since the window's keys come from it.
+        keyboard: Arc::new(Mutex::new(KeyboardInput::with_key_check(|name| Keycode::from_name(name).is_some()))),
+        pads: Arc::new(Mutex::new(SdlPads::default())),
+    };
+    let cancel_copy = cancel.clone();
+    if let Err(e) = ctrlc::set_handler(move || cancel_copy.cancel()) {
+        warn!("Could not set up exit signal handler: {}", e);
+    }
 
     let state_mutex_copy = state_mutex.clone();
-    // Start display thread.
-    thread::spawn(move || {
-        let mut iw = InputWindow::new(&theme, state_mutex_copy).unwrap_or_barf("Could not make window");
-        match iw.run(base, theme) {
-            Err(e) => println!("Error in display thread: {}", e),
-            _ => {}
+    let heatmaps_mutex_copy = heatmaps_mutex.clone();
+    let context_copy = context.clone();
+    let read_pads = match input {
+        InputSource::Sdl(_) => true,
+        _ => false,
+    };
+    // Start display thread, unless we're just passing input along.
+    let display_thread = if let (Some(theme), Some(base)) = (conf.theme, conf.theme_path) {
+        Some(thread::spawn(move || {
+            let mut iw = InputWindow::new(&theme, &window_conf, state_mutex_copy, heatmaps_mutex_copy, context_copy.clone(), read_pads).unwrap_or_barf("Could not make window");
+            match iw.run(base, theme) {
+                Err(e) => error!("Error in display thread: {}", e),
+                _ => {}
+            }
+            // Stop the input-reader loop when display thread has ended.
+            context_copy.cancel.cancel();
+        }))
+    }
+    else {
+        info!("Running without a window");
+        if let InputSource::Sdl(_) = input {
+            spawn_pad_thread(&context);
         }
-        // Send done signal when display thread has ended.
-        done_sender.send(()).unwrap_or_barf("Could not send done signal");
-    });
+        None
+    };
 
     // Input-reader loop.
-    loop {
-        // Check if display thread has ended.
-        if done_receiver.try_recv().is_ok() {
-            break;
-        }
+    while !cancel.is_cancelled() {
         // Read new input.
-        let new_state = reader.read_next_input().unwrap_or_barf("Error reading input");
+        let new_state = match read_input(&mut reader, &input, &context) {
+            Ok(new_state) => {
+                if backoff.failures() > 0 {
+                    info!("{} input is working again", input.name());
+                    state_mutex.lock().unwrap_or_barf("Error updating controller state").status = None;
+                    backoff.succeeded();
+                }
+                transform.apply(new_state)
+            }
+            Err(_) if cancel.is_cancelled() => break,
+            Err(e) => {
+                let e = Error::ReaderError(input.name(), Box::new(e));
+                warn!("{}", e);
+                state_mutex.lock().unwrap_or_barf("Error updating controller state").status = Some(e.to_string());
+                let delay = backoff.failed();
+                if backoff.should_reopen() {
+                    reader = None;
+                }
+                // Wait before trying again, unless we're told to stop.
+                cancel.sleep(delay);
+                continue;
+            }
+        };
+        let now = Instant::now();
+        let (connected, mut metadata) = match reader {
+            Some(ref reader) => (reader.is_connected(), reader.metadata()),
+            None => (false, Metadata::new()),
+        };
+        if connected {
+            poll_stats.record(now);
+        }
+        else {
+            poll_stats.pause();
+        }
+        if let Some(interval) = poll_log_interval {
+            if now.duration_since(last_poll_log) >= interval {
+                info!("Polling: {}", poll_stats.summary());
+                last_poll_log = now;
+            }
+        }
+        let mut stats = stats_mutex.lock().unwrap_or_barf("Error updating stats");
+        stats.update(&new_state, now);
+        for (output, last_error) in outputs.iter_mut().zip(output_errors.iter_mut()) {
+            write_output(&mut **output, last_error, &new_state);
+        }
+
+        // Everything else is just for the window.
+        if display_thread.is_none() {
+            continue;
+        }
+        stats.add_metadata(&mut metadata);
+        poll_stats.add_metadata(&mut metadata);
+        {
+            let mut heatmaps = heatmaps_mutex.lock().unwrap_or_barf("Error updating heatmaps");
+            if connected {
+                heatmaps.record(&new_state);
+            }
+            heatmaps.add_metadata(&mut metadata);
+        }
         // Update mutex.
         let mut state = state_mutex.lock().unwrap_or_barf("Error updating controller state");
-        *state = new_state;
+        state.controller = new_state;
+        state.metadata = metadata;
+        state.disconnected = !connected;
+        if connected {
+            state.analog_trail.record(new_state.analog);
+            state.c_trail.record(new_state.c);
+        }
     }
-}
 
-#[cfg(windows)]
-fn get_input(input: InputSource) -> Box<dyn InputReader> {
-    match input {
-        InputSource::Dtm(path) => {
-            let dtm_reader = DtmReader::from_path(&path).unwrap_or_barf("Could not open dtm file");
-            Box::new(dtm_reader)
-        }
-        InputSource::Sa2(exe_name) => {
-            let sa2_reader = Sa2Reader::new(exe_name).unwrap_or_barf("Could not open SA2 reader");
-            Box::new(sa2_reader)
+    info!("Shutting down");
+    if let Some(display_thread) = display_thread {
+        if display_thread.join().is_err() {
+            error!("Display thread panicked");
         }
-        InputSource::Serial(path) => {
-            let serial_reader = SerialReader::from_path(&path).unwrap_or_barf("Could not open serial port");
-            Box::new(serial_reader)
+    }
+    // Close the input, along with any serial capture it was writing.
+    drop(reader);
+
+    // Save or flush whatever the outputs have left.
+    for output in outputs.iter_mut() {
+        if let Err(e) = output.finish() {
+            error!("Could not finish output: {}", e);
         }
     }
+    log::logger().flush();
 }
 
-#[cfg(not(windows))]
-fn get_input(input: InputSource) -> Box<dyn InputReader> {
-    match input {
-        InputSource::Dtm(path) => {
-            let dtm_reader = DtmReader::from_path(&path).unwrap_or_barf("Could not open dtm file");
-            Box::new(dtm_reader)
+/// Runs SDL just to keep the sdl input method's pads up to date, for when
+/// there's no window doing it.
+fn spawn_pad_thread(context: &ReaderContext) {
+    let pads = context.pads.clone();
+    let cancel = context.cancel.clone();
+    thread::spawn(move || {
+        if let Err(e) = run_pads(pads, &cancel) {
+            error!("Could not read pads: {}", e);
         }
-        InputSource::Sa2(exe_name_opt) => {
-            barf("Process memory reading only available on Windows")
+    });
+}
+
+fn run_pads(pads: Arc<Mutex<SdlPads>>, cancel: &CancelToken) -> gc_input::Result<()> {
+    let sdl = sdl2::init()?;
+    let mut bridge = PadBridge::new(&sdl, pads)?;
+    let mut event_pump = sdl.event_pump()?;
+    while !cancel.sleep(Duration::from_micros(1_000_000 / 120)) {
+        for event in event_pump.poll_iter() {
+            bridge.handle_event(&event);
+        }
+        bridge.update();
+    }
+    Ok(())
+}
+
+/// Reads from `reader`, opening it from `input` first if needed.
+fn read_input(reader: &mut Option<Box<dyn InputReader>>, input: &InputSource, context: &ReaderContext) -> gc_input::Result<ControllerState> {
+    let opened = match reader.take() {
+        Some(opened) => opened,
+        None => input_reader::from_source(input.clone(), context)?,
+    };
+    reader.get_or_insert(opened).read_next_input()
+}
+
+/// Sends `state` to `output`, logging any error unless it's the same as the
+/// last one.
+fn write_output(output: &mut dyn OutputSink, last_error: &mut Option<String>, state: &ControllerState) {
+    match output.write_state(state) {
+        Ok(()) => *last_error = None,
+        Err(e) => {
+            let message = e.to_string();
+            if last_error.as_ref() != Some(&message) {
+                warn!("Error writing output: {}", message);
+                *last_error = Some(message);
+            }
         }
-        InputSource::Serial(path) => {
-            let serial_reader = SerialReader::from_path(&path).unwrap_or_barf("Could not open serial port");
-            Box::new(serial_reader)
+    }
+}
+
+fn list_ports() {
+    let ports = serialport::available_ports().unwrap_or_barf("Could not list serial ports");
+    if ports.is_empty() {
+        println!("No serial ports found.");
+    }
+
+    for port in ports {
+        println!("{}", port.port_name);
+        match port.port_type {
+            SerialPortType::UsbPort(info) => {
+                println!("    USB {:04x}:{:04x}", info.vid, info.pid);
+                if let Some(manufacturer) = info.manufacturer {
+                    println!("    Manufacturer: {}", manufacturer);
+                }
+                if let Some(product) = info.product {
+                    println!("    Product: {}", product);
+                }
+                if let Some(serial_number) = info.serial_number {
+                    println!("    Serial number: {}", serial_number);
+                }
+            }
+            SerialPortType::PciPort => println!("    PCI"),
+            SerialPortType::BluetoothPort => println!("    Bluetooth"),
+            SerialPortType::Unknown => println!("    Unknown type"),
         }
     }
 }
 
 fn barf(message: &str) -> ! {
-    println!("Error: {}", message);
+    // This can happen before logging is set up.
+    if log::max_level() == LevelFilter::Off {
+        println!("Error: {}", message);
+    }
+    else {
+        error!("{}", message);
+        log::logger().flush();
+    }
     process::exit(1);
 }
 
@@ -109,7 +348,7 @@ trait UnwrapOrBarf<T> {
 }
 
 impl<T, E> UnwrapOrBarf<T> for Result<T, E>
-    where E: Error,
+    where E: error::Error,
 {
     fn unwrap_or_barf(self, message: &str) -> T {
         self.unwrap_or_else(|err| {
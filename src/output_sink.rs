@@ -0,0 +1,67 @@
+pub mod dolphin_pipe_sink;
+pub mod network_sink;
+pub mod recorder_sink;
+pub mod stats_sink;
+#[cfg(target_os = "linux")]
+pub mod uinput_sink;
+
+use crate::configuration::{OutputConf, UinputConf};
+use crate::controller_state::{Button, ControllerState};
+#[cfg(not(target_os = "linux"))]
+use crate::error::Error;
+use crate::error::Result;
+use self::dolphin_pipe_sink::DolphinPipeSink;
+use self::network_sink::NetworkSink;
+use self::recorder_sink::RecorderSink;
+use self::stats_sink::StatsSink;
+#[cfg(target_os = "linux")]
+use self::uinput_sink::{UinputSink, DEFAULT_NAME};
+
+/// Somewhere the controller state goes after every input, such as an
+/// emulator or a virtual controller.
+pub trait OutputSink {
+    fn write_state(&mut self, state: &ControllerState) -> Result<()>;
+
+    /// Called once when the viewer exits, to save or flush anything left.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sets up whatever output `conf` describes.
+pub fn from_conf(conf: OutputConf) -> Result<Box<dyn OutputSink>> {
+    match conf {
+        OutputConf::Recorder(path) => Ok(Box::new(RecorderSink::create(path)?)),
+        OutputConf::Network(address) => Ok(Box::new(NetworkSink::new(&address)?)),
+        OutputConf::Stats(path) => Ok(Box::new(StatsSink::new(path))),
+        OutputConf::DolphinPipe(path) => Ok(Box::new(DolphinPipeSink::new(path))),
+        OutputConf::Uinput(uinput) => open_uinput(uinput.unwrap_or_default()),
+    }
+}
+
+/// Every button and axis of `state` by the names used in text templates,
+/// with buttons as 0 or 1 and axes in raw units.
+pub fn state_fields(state: &ControllerState) -> Vec<(&'static str, i64)> {
+    let mut fields: Vec<(&'static str, i64)> = Button::ALL.iter()
+        .map(|&button| (button.name(), state.button(button) as i64))
+        .collect();
+    fields.push(("analog.x", state.analog.0 as i64));
+    fields.push(("analog.y", state.analog.1 as i64));
+    fields.push(("c.x", state.c.0 as i64));
+    fields.push(("c.y", state.c.1 as i64));
+    fields.push(("l_analog", state.l_analog as i64));
+    fields.push(("r_analog", state.r_analog as i64));
+    fields
+}
+
+#[cfg(target_os = "linux")]
+fn open_uinput(conf: UinputConf) -> Result<Box<dyn OutputSink>> {
+    let name = conf.name.as_ref().map(|name| name.as_str()).unwrap_or(DEFAULT_NAME);
+    let uinput_sink = UinputSink::create(name)?;
+    Ok(Box::new(uinput_sink))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_uinput(_conf: UinputConf) -> Result<Box<dyn OutputSink>> {
+    Err(Error::UnsupportedPlatform("virtual controllers are only available on Linux"))
+}
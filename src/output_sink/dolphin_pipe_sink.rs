@@ -0,0 +1,307 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+
+use crate::controller_state::{Button, ControllerState};
+use crate::error::Result;
+use crate::output_sink::OutputSink;
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// Buttons and what Dolphin's pipe input calls them.
+pub const PIPE_BUTTONS: [(Button, &'static str); 12] = [
+    (Button::A, "A"),
+    (Button::B, "B"),
+    (Button::X, "X"),
+    (Button::Y, "Y"),
+    (Button::Z, "Z"),
+    (Button::Start, "START"),
+    (Button::LDigital, "L"),
+    (Button::RDigital, "R"),
+    (Button::Up, "D_UP"),
+    (Button::Down, "D_DOWN"),
+    (Button::Left, "D_LEFT"),
+    (Button::Right, "D_RIGHT"),
+];
+
+/// Drives Dolphin through one of its input pipes, sending only what changed
+/// since the last state. Dolphin doesn't have to be running yet; the pipe
+/// gets opened once it is, and again if Dolphin is restarted.
+pub struct DolphinPipeSink {
+    path: PathBuf,
+    pipe: Option<File>,
+    last: Option<ControllerState>,
+    last_attempt: Option<Instant>,
+}
+
+impl DolphinPipeSink {
+    pub fn new(path: PathBuf) -> DolphinPipeSink {
+        DolphinPipeSink {
+            path: path,
+            pipe: None,
+            last: None,
+            last_attempt: None,
+        }
+    }
+
+    /// Tries to open the pipe if it isn't open, at most once a second.
+    fn connect(&mut self) {
+        if self.pipe.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        if self.last_attempt.map_or(false, |attempt| now.duration_since(attempt) < RECONNECT_INTERVAL) {
+            return;
+        }
+        self.last_attempt = Some(now);
+
+        match open_pipe(&self.path) {
+            Ok(pipe) => {
+                info!("Writing input to Dolphin pipe {}", self.path.display());
+                self.pipe = Some(pipe);
+                // Dolphin doesn't know anything about the controller yet.
+                self.last = None;
+            }
+            Err(e) => debug!("Could not open Dolphin pipe {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+impl OutputSink for DolphinPipeSink {
+    fn write_state(&mut self, state: &ControllerState) -> Result<()> {
+        self.connect();
+        let commands = commands(self.last.as_ref(), state);
+        let result = match self.pipe {
+            Some(ref mut pipe) => pipe.write(commands.as_bytes()),
+            None => return Ok(()),
+        };
+
+        match result {
+            Ok(len) if len == commands.len() => {
+                self.last = Some(*state);
+                Ok(())
+            }
+            Ok(_) => {
+                // Writes this small go through whole or not at all, so this
+                // shouldn't happen. Dolphin is left with half a command if it
+                // does, so start over with a fresh pipe.
+                info!("Could not write everything to Dolphin pipe {}", self.path.display());
+                self.pipe = None;
+                Ok(())
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // Dolphin is behind. Leaving `last` alone means the next write
+                // catches it up with everything that changed in the meantime.
+                debug!("Dolphin pipe {} is full", self.path.display());
+                Ok(())
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                info!("Dolphin closed pipe {}", self.path.display());
+                self.pipe = None;
+                self.last_attempt = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.pipe = None;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// The pipe commands that take Dolphin from `last` to `state`, one per line.
+/// Everything is sent if there's no `last`.
+pub fn commands(last: Option<&ControllerState>, state: &ControllerState) -> String {
+    let mut commands = String::new();
+    for &(button, name) in PIPE_BUTTONS.iter() {
+        let pressed = state.button(button);
+        if last.map_or(true, |last| last.button(button) != pressed) {
+            let action = if pressed { "PRESS" } else { "RELEASE" };
+            commands.push_str(&format!("{} {}\n", action, name));
+        }
+    }
+    if last.map_or(true, |last| last.analog != state.analog) {
+        commands.push_str(&format!("SET MAIN {:.3} {:.3}\n", stick_value(state.analog.0), stick_value(state.analog.1)));
+    }
+    if last.map_or(true, |last| last.c != state.c) {
+        commands.push_str(&format!("SET C {:.3} {:.3}\n", stick_value(state.c.0), stick_value(state.c.1)));
+    }
+    if last.map_or(true, |last| last.l_analog != state.l_analog) {
+        commands.push_str(&format!("SET L {:.3}\n", state.l_analog as f32 / 255.0));
+    }
+    if last.map_or(true, |last| last.r_analog != state.r_analog) {
+        commands.push_str(&format!("SET R {:.3}\n", state.r_analog as f32 / 255.0));
+    }
+    commands
+}
+
+/// Dolphin takes sticks from 0 to 1 with 0.5 in the center.
+fn stick_value(raw: u8) -> f32 {
+    (0.5 + (raw as f32 - 128.0) / 254.0).max(0.0).min(1.0)
+}
+
+/// Opens the pipe without waiting for Dolphin, which fails if Dolphin isn't
+/// reading from it yet. Writes don't wait either, so a stalled Dolphin can't
+/// hold up the input thread.
+#[cfg(unix)]
+fn open_pipe(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_pipe(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).open(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::ffi::CString;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, Read};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+
+    /// Makes a FIFO to stand in for Dolphin's pipe.
+    fn make_fifo(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gc_input_{}_{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pipe");
+        let _ = fs::remove_file(&path);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        path
+    }
+
+    fn remove_fifo(path: &PathBuf) {
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    /// Opens the reading end the way Dolphin would, minus the waiting.
+    fn open_dolphin(path: &PathBuf) -> File {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    fn read_available(pipe: &mut File) -> String {
+        let mut read = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => read.extend_from_slice(&buf[..len]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        String::from_utf8(read).unwrap()
+    }
+
+    /// The last command sent for each button, stick and trigger.
+    fn final_commands(sent: &str) -> BTreeMap<String, String> {
+        sent.lines()
+            .map(|line| {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                let control = if words[0] == "SET" {
+                    format!("SET {}", words[1])
+                }
+                else {
+                    words[1].to_string()
+                };
+                (control, line.to_string())
+            })
+            .collect()
+    }
+
+    fn moved_state() -> ControllerState {
+        ControllerState {
+            a: true,
+            analog: (200, 60),
+            r_analog: 255,
+            ..ControllerState::default()
+        }
+    }
+
+    #[test]
+    fn sends_everything_then_changes() {
+        let path = make_fifo("pipe_changes");
+        let mut dolphin = open_dolphin(&path);
+        let mut sink = DolphinPipeSink::new(path.clone());
+
+        let first = ControllerState::default();
+        sink.write_state(&first).unwrap();
+        let sent = read_available(&mut dolphin);
+        assert_eq!(sent, commands(None, &first));
+        assert_eq!(sent.lines().count(), PIPE_BUTTONS.len() + 4);
+        assert!(sent.contains("RELEASE A\n"));
+        assert!(sent.contains("SET MAIN 0.500 0.500\n"));
+
+        let second = moved_state();
+        sink.write_state(&second).unwrap();
+        assert_eq!(read_available(&mut dolphin), "PRESS A\nSET MAIN 0.783 0.232\nSET R 1.000\n");
+
+        sink.write_state(&second).unwrap();
+        assert_eq!(read_available(&mut dolphin), "");
+
+        remove_fifo(&path);
+    }
+
+    #[test]
+    fn resends_everything_after_reconnecting() {
+        let path = make_fifo("pipe_reconnect");
+        let mut dolphin = open_dolphin(&path);
+        let mut sink = DolphinPipeSink::new(path.clone());
+
+        sink.write_state(&ControllerState::default()).unwrap();
+        read_available(&mut dolphin);
+
+        // Dolphin restarts, so the next write finds the pipe closed.
+        drop(dolphin);
+        let state = moved_state();
+        sink.write_state(&state).unwrap();
+        let mut dolphin = open_dolphin(&path);
+
+        sink.write_state(&state).unwrap();
+        assert_eq!(read_available(&mut dolphin), commands(None, &state));
+
+        remove_fifo(&path);
+    }
+
+    #[test]
+    fn catches_up_instead_of_blocking_when_full() {
+        let path = make_fifo("pipe_full");
+        let mut dolphin = open_dolphin(&path);
+        let mut sink = DolphinPipeSink::new(path.clone());
+
+        // Far more than a pipe holds, with nothing reading it.
+        let mut state = ControllerState::default();
+        for i in 0..100_000 {
+            state.a = i % 2 == 0;
+            sink.write_state(&state).unwrap();
+        }
+
+        let mut sent = read_available(&mut dolphin);
+        let last = moved_state();
+        sink.write_state(&last).unwrap();
+        sent.push_str(&read_available(&mut dolphin));
+
+        assert_eq!(final_commands(&sent), final_commands(&commands(None, &last)));
+
+        remove_fifo(&path);
+    }
+}
@@ -0,0 +1,47 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use log::info;
+use serde_json::{Map, Value};
+
+use crate::controller_state::ControllerState;
+use crate::error::{Error, Result};
+use crate::output_sink::{state_fields, OutputSink};
+
+/// Sends every controller state to another machine as a UDP packet holding
+/// a JSON object, for overlays or tools running somewhere else.
+pub struct NetworkSink {
+    socket: UdpSocket,
+    address: SocketAddr,
+}
+
+impl NetworkSink {
+    /// Looks `address` up once, so a bad address fails here instead of on
+    /// every input.
+    pub fn new(address: &str) -> Result<NetworkSink> {
+        let resolved = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::FormatError(format!("could not resolve {}", address)))?;
+        let socket = match resolved {
+            SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+            SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+        };
+        info!("Sending input to {} ({})", address, resolved);
+
+        Ok(NetworkSink {
+            socket: socket,
+            address: resolved,
+        })
+    }
+}
+
+impl OutputSink for NetworkSink {
+    fn write_state(&mut self, state: &ControllerState) -> Result<()> {
+        let mut object = Map::new();
+        for (name, value) in state_fields(state) {
+            object.insert(name.to_string(), value.into());
+        }
+        let packet = serde_json::to_vec(&Value::Object(object))?;
+        self.socket.send_to(&packet, self.address)?;
+        Ok(())
+    }
+}
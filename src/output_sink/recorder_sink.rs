@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use log::info;
+
+use crate::controller_state::ControllerState;
+use crate::error::Result;
+use crate::output_sink::{state_fields, OutputSink};
+
+/// Saves every change to the controller state as a row of a CSV file, with
+/// the time in milliseconds since recording started.
+pub struct RecorderSink {
+    file: BufWriter<File>,
+    start: Instant,
+    last: Option<ControllerState>,
+}
+
+impl RecorderSink {
+    pub fn create<P>(path: P) -> Result<RecorderSink>
+        where P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut file = BufWriter::new(File::create(path)?);
+        let header: Vec<&str> = state_fields(&ControllerState::default()).iter().map(|&(name, _)| name).collect();
+        writeln!(file, "time_ms,{}", header.join(","))?;
+        info!("Recording input to {}", path.display());
+
+        Ok(RecorderSink {
+            file: file,
+            start: Instant::now(),
+            last: None,
+        })
+    }
+}
+
+impl OutputSink for RecorderSink {
+    fn write_state(&mut self, state: &ControllerState) -> Result<()> {
+        if self.last.as_ref() == Some(state) {
+            return Ok(());
+        }
+
+        let elapsed = self.start.elapsed();
+        let millis = elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64;
+        let values: Vec<String> = state_fields(state).iter().map(|&(_, value)| value.to_string()).collect();
+        writeln!(self.file, "{},{}", millis, values.join(","))?;
+        self.last = Some(*state);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use log::info;
+
+use crate::controller_state::ControllerState;
+use crate::error::Result;
+use crate::output_sink::OutputSink;
+use crate::stats::SessionStats;
+
+/// Keeps track of the session's stats and saves them when the viewer exits.
+pub struct StatsSink {
+    stats: Arc<Mutex<SessionStats>>,
+    path: PathBuf,
+    /// Whether this sink keeps the stats up to date, rather than whoever
+    /// handed them over.
+    updates: bool,
+}
+
+impl StatsSink {
+    pub fn new(path: PathBuf) -> StatsSink {
+        StatsSink {
+            stats: Arc::new(Mutex::new(SessionStats::new())),
+            path: path,
+            updates: true,
+        }
+    }
+
+    /// Saves `stats` without touching them otherwise, for when something
+    /// else is already updating them with every input.
+    pub fn saving(path: PathBuf, stats: Arc<Mutex<SessionStats>>) -> StatsSink {
+        StatsSink {
+            stats: stats,
+            path: path,
+            updates: false,
+        }
+    }
+}
+
+impl OutputSink for StatsSink {
+    fn write_state(&mut self, state: &ControllerState) -> Result<()> {
+        if self.updates {
+            self.stats.lock().unwrap_or_else(|e| e.into_inner()).update(state, Instant::now());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).save(&self.path)?;
+        info!("Saved stats to {}", self.path.display());
+        Ok(())
+    }
+}
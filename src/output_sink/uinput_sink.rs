@@ -0,0 +1,351 @@
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::slice;
+
+use log::info;
+
+use crate::controller_state::{Button, ControllerState};
+use crate::error::Result;
+use crate::output_sink::OutputSink;
+
+const UINPUT_PATH: &'static str = "/dev/uinput";
+pub const DEFAULT_NAME: &'static str = "GC Input Viewer";
+
+// From linux/input-event-codes.h.
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_ABS: u16 = 0x03;
+pub const SYN_REPORT: u16 = 0x00;
+pub const BTN_SOUTH: u16 = 0x130;
+pub const BTN_EAST: u16 = 0x131;
+pub const BTN_NORTH: u16 = 0x133;
+pub const BTN_WEST: u16 = 0x134;
+pub const BTN_TR: u16 = 0x137;
+pub const BTN_TL2: u16 = 0x138;
+pub const BTN_TR2: u16 = 0x139;
+pub const BTN_START: u16 = 0x13b;
+pub const BTN_DPAD_UP: u16 = 0x220;
+pub const BTN_DPAD_DOWN: u16 = 0x221;
+pub const BTN_DPAD_LEFT: u16 = 0x222;
+pub const BTN_DPAD_RIGHT: u16 = 0x223;
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_Z: u16 = 0x02;
+pub const ABS_RX: u16 = 0x03;
+pub const ABS_RY: u16 = 0x04;
+pub const ABS_RZ: u16 = 0x05;
+
+// From linux/uinput.h.
+const UI_DEV_CREATE: u32 = 0x5501;
+const UI_DEV_DESTROY: u32 = 0x5502;
+const UI_SET_EVBIT: u32 = 0x4004_5564;
+const UI_SET_KEYBIT: u32 = 0x4004_5565;
+const UI_SET_ABSBIT: u32 = 0x4004_5567;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// Buttons and the gamepad buttons they show up as, laid out the same way as
+/// the defaults for the sdl input method.
+pub const UINPUT_BUTTONS: [(Button, u16); 12] = [
+    (Button::A, BTN_SOUTH),
+    (Button::B, BTN_EAST),
+    (Button::X, BTN_WEST),
+    (Button::Y, BTN_NORTH),
+    (Button::Z, BTN_TR),
+    (Button::LDigital, BTN_TL2),
+    (Button::RDigital, BTN_TR2),
+    (Button::Start, BTN_START),
+    (Button::Up, BTN_DPAD_UP),
+    (Button::Down, BTN_DPAD_DOWN),
+    (Button::Left, BTN_DPAD_LEFT),
+    (Button::Right, BTN_DPAD_RIGHT),
+];
+pub const UINPUT_AXES: [u16; 6] = [ABS_X, ABS_Y, ABS_RX, ABS_RY, ABS_Z, ABS_RZ];
+
+/// One event for the virtual controller.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    fn new(kind: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent {
+            kind: kind,
+            code: code,
+            value: value,
+        }
+    }
+}
+
+/// Where the virtual controller's events go. `UinputDevice` sends them to a
+/// real one; anything else can collect them to check what would be sent.
+pub trait EventEmitter {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+}
+
+/// A virtual gamepad made through uinput. It goes away when this is dropped.
+pub struct UinputDevice {
+    file: File,
+}
+
+impl UinputDevice {
+    pub fn create(name: &str) -> Result<UinputDevice> {
+        let file = OpenOptions::new().write(true).open(UINPUT_PATH)?;
+        let device = UinputDevice {
+            file: file,
+        };
+
+        device.ioctl(UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+        for &(_, code) in UINPUT_BUTTONS.iter() {
+            device.ioctl(UI_SET_KEYBIT, code as libc::c_int)?;
+        }
+        device.ioctl(UI_SET_EVBIT, EV_ABS as libc::c_int)?;
+        for &code in UINPUT_AXES.iter() {
+            device.ioctl(UI_SET_ABSBIT, code as libc::c_int)?;
+        }
+
+        let mut setup: libc::uinput_user_dev = unsafe { mem::zeroed() };
+        let name = CString::new(name).unwrap_or_else(|_| CString::new(DEFAULT_NAME).unwrap());
+        for (dst, &src) in setup.name.iter_mut().zip(name.as_bytes().iter().take(libc::UINPUT_MAX_NAME_SIZE - 1)) {
+            *dst = src as libc::c_char;
+        }
+        setup.id.bustype = BUS_VIRTUAL;
+        setup.id.version = 1;
+        // Every axis uses the same raw units as the rest of the viewer.
+        for &code in UINPUT_AXES.iter() {
+            setup.absmin[code as usize] = 0;
+            setup.absmax[code as usize] = 255;
+        }
+        let setup_bytes = unsafe {
+            slice::from_raw_parts(&setup as *const _ as *const u8, mem::size_of::<libc::uinput_user_dev>())
+        };
+        (&device.file).write_all(setup_bytes)?;
+
+        device.ioctl(UI_DEV_CREATE, 0)?;
+        info!("Created virtual controller {}", name.to_string_lossy());
+        Ok(device)
+    }
+
+    fn ioctl(&self, request: u32, arg: libc::c_int) -> io::Result<()> {
+        let result = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+impl EventEmitter for UinputDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(events.len() * mem::size_of::<libc::input_event>());
+        for event in events.iter() {
+            // The kernel fills in the time.
+            let mut raw: libc::input_event = unsafe { mem::zeroed() };
+            raw.type_ = event.kind;
+            raw.code = event.code;
+            raw.value = event.value;
+            bytes.extend_from_slice(unsafe {
+                slice::from_raw_parts(&raw as *const _ as *const u8, mem::size_of::<libc::input_event>())
+            });
+        }
+        self.file.write_all(&bytes)
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        let _ = self.ioctl(UI_DEV_DESTROY, 0);
+    }
+}
+
+/// Mirrors the controller state into a virtual gamepad, so games and
+/// emulators that take gamepad input can be driven by any input method.
+pub struct UinputSink<E> {
+    emitter: E,
+    last: Option<ControllerState>,
+}
+
+impl UinputSink<UinputDevice> {
+    pub fn create(name: &str) -> Result<UinputSink<UinputDevice>> {
+        Ok(UinputSink::new(UinputDevice::create(name)?))
+    }
+}
+
+impl<E> UinputSink<E>
+    where E: EventEmitter,
+{
+    pub fn new(emitter: E) -> UinputSink<E> {
+        UinputSink {
+            emitter: emitter,
+            last: None,
+        }
+    }
+}
+
+impl<E> OutputSink for UinputSink<E>
+    where E: EventEmitter,
+{
+    fn write_state(&mut self, state: &ControllerState) -> Result<()> {
+        let events = events(self.last.as_ref(), state);
+        if !events.is_empty() {
+            self.emitter.emit(&events)?;
+        }
+        self.last = Some(*state);
+        Ok(())
+    }
+}
+
+/// The events that take the virtual controller from `last` to `state`,
+/// ending with a sync. Everything is sent if there's no `last`, and nothing
+/// at all if nothing changed.
+pub fn events(last: Option<&ControllerState>, state: &ControllerState) -> Vec<InputEvent> {
+    let mut events = Vec::new();
+    for &(button, code) in UINPUT_BUTTONS.iter() {
+        let pressed = state.button(button);
+        if last.map_or(true, |last| last.button(button) != pressed) {
+            events.push(InputEvent::new(EV_KEY, code, pressed as i32));
+        }
+    }
+
+    let axes = |state: &ControllerState| [
+        (ABS_X, state.analog.0 as i32),
+        (ABS_Y, flip_axis(state.analog.1)),
+        (ABS_RX, state.c.0 as i32),
+        (ABS_RY, flip_axis(state.c.1)),
+        (ABS_Z, state.l_analog as i32),
+        (ABS_RZ, state.r_analog as i32),
+    ];
+    let last_axes = last.map(|last| axes(last));
+    for (i, &(code, value)) in axes(state).iter().enumerate() {
+        if last_axes.map_or(true, |last_axes| last_axes[i].1 != value) {
+            events.push(InputEvent::new(EV_ABS, code, value));
+        }
+    }
+
+    if !events.is_empty() {
+        events.push(InputEvent::new(EV_SYN, SYN_REPORT, 0));
+    }
+    events
+}
+
+/// Down is positive for gamepads, so the up and down axes get flipped around
+/// the center, keeping a centered stick centered.
+fn flip_axis(raw: u8) -> i32 {
+    (256 - raw as i32).min(255)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps every batch of events instead of sending them anywhere.
+    #[derive(Default)]
+    struct Collector {
+        batches: Vec<Vec<InputEvent>>,
+    }
+
+    impl EventEmitter for &mut Collector {
+        fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            self.batches.push(events.to_vec());
+            Ok(())
+        }
+    }
+
+    fn value(events: &[InputEvent], kind: u16, code: u16) -> Option<i32> {
+        events.iter().find(|event| event.kind == kind && event.code == code).map(|event| event.value)
+    }
+
+    #[test]
+    fn first_state_sends_everything() {
+        let mut collector = Collector::default();
+        UinputSink::new(&mut collector).write_state(&ControllerState::default()).unwrap();
+
+        assert_eq!(collector.batches.len(), 1);
+        let events = &collector.batches[0];
+        assert_eq!(events.len(), UINPUT_BUTTONS.len() + UINPUT_AXES.len() + 1);
+        for &(_, code) in UINPUT_BUTTONS.iter() {
+            assert_eq!(value(events, EV_KEY, code), Some(0));
+        }
+        for &code in UINPUT_AXES.iter() {
+            assert!(value(events, EV_ABS, code).is_some());
+        }
+    }
+
+    #[test]
+    fn later_states_send_only_changes() {
+        let mut collector = Collector::default();
+        {
+            let mut sink = UinputSink::new(&mut collector);
+            let mut state = ControllerState::default();
+            sink.write_state(&state).unwrap();
+            state.b = true;
+            state.l_analog = 100;
+            sink.write_state(&state).unwrap();
+        }
+
+        assert_eq!(collector.batches.len(), 2);
+        assert_eq!(collector.batches[1], vec![
+            InputEvent::new(EV_KEY, BTN_EAST, 1),
+            InputEvent::new(EV_ABS, ABS_Z, 100),
+            InputEvent::new(EV_SYN, SYN_REPORT, 0),
+        ]);
+    }
+
+    #[test]
+    fn nothing_is_sent_without_changes() {
+        let mut collector = Collector::default();
+        {
+            let mut sink = UinputSink::new(&mut collector);
+            let state = ControllerState::default();
+            sink.write_state(&state).unwrap();
+            sink.write_state(&state).unwrap();
+        }
+
+        assert_eq!(collector.batches.len(), 1);
+        let state = ControllerState::default();
+        assert!(events(Some(&state), &state).is_empty());
+    }
+
+    #[test]
+    fn every_batch_ends_with_a_sync() {
+        let mut state = ControllerState::default();
+        let first = events(None, &state);
+        state.start = true;
+        let second = events(Some(&ControllerState::default()), &state);
+
+        for events in [first, second].iter() {
+            assert_eq!(events.last(), Some(&InputEvent::new(EV_SYN, SYN_REPORT, 0)));
+            assert_eq!(events.iter().filter(|event| event.kind == EV_SYN).count(), 1);
+        }
+    }
+
+    #[test]
+    fn up_and_down_are_flipped_around_the_center() {
+        let mut state = ControllerState {
+            analog: (128, 128),
+            c: (128, 128),
+            ..ControllerState::default()
+        };
+        let centered = events(None, &state);
+        assert_eq!(value(&centered, EV_ABS, ABS_Y), Some(128));
+        assert_eq!(value(&centered, EV_ABS, ABS_RY), Some(128));
+
+        state.analog = (200, 255);
+        state.c = (0, 0);
+        let pushed = events(None, &state);
+        assert_eq!(value(&pushed, EV_ABS, ABS_X), Some(200));
+        assert_eq!(value(&pushed, EV_ABS, ABS_Y), Some(1));
+        assert_eq!(value(&pushed, EV_ABS, ABS_RX), Some(0));
+        assert_eq!(value(&pushed, EV_ABS, ABS_RY), Some(255));
+
+        state.analog.1 = 28;
+        assert_eq!(value(&events(None, &state), EV_ABS, ABS_Y), Some(228));
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use sdl2::controller::{Axis, Button as PadButton, GameController};
+use sdl2::event::Event;
+use sdl2::joystick::Joystick;
+use sdl2::{GameControllerSubsystem, JoystickSubsystem, Sdl};
+
+use gc_input::error::Result;
+use gc_input::input_reader::sdl_reader::{PadState, SdlPads};
+
+const AXES: [Axis; 6] = [
+    Axis::LeftX,
+    Axis::LeftY,
+    Axis::RightX,
+    Axis::RightY,
+    Axis::TriggerLeft,
+    Axis::TriggerRight,
+];
+const PAD_BUTTONS: [PadButton; 15] = [
+    PadButton::A,
+    PadButton::B,
+    PadButton::X,
+    PadButton::Y,
+    PadButton::Back,
+    PadButton::Guide,
+    PadButton::Start,
+    PadButton::LeftStick,
+    PadButton::RightStick,
+    PadButton::LeftShoulder,
+    PadButton::RightShoulder,
+    PadButton::DPadUp,
+    PadButton::DPadDown,
+    PadButton::DPadLeft,
+    PadButton::DPadRight,
+];
+
+/// A pad opened as a game controller if SDL knows its layout, or as a raw
+/// joystick if not.
+enum OpenPad {
+    Controller(GameController),
+    Joystick(Joystick),
+}
+
+impl OpenPad {
+    fn instance_id(&self) -> i32 {
+        match *self {
+            OpenPad::Controller(ref controller) => controller.instance_id(),
+            OpenPad::Joystick(ref joystick) => joystick.instance_id(),
+        }
+    }
+
+    fn name(&self) -> String {
+        match *self {
+            OpenPad::Controller(ref controller) => controller.name(),
+            OpenPad::Joystick(ref joystick) => joystick.name(),
+        }
+    }
+
+    fn is_mapped(&self) -> bool {
+        match *self {
+            OpenPad::Controller(_) => true,
+            OpenPad::Joystick(_) => false,
+        }
+    }
+
+    fn state(&self, guid: &str) -> PadState {
+        let (axes, buttons, hats) = match *self {
+            OpenPad::Controller(ref controller) => {
+                let mut axes = vec![0; AXES.len()];
+                for &axis in AXES.iter() {
+                    axes[axis as usize] = controller.axis(axis);
+                }
+                let mut buttons = vec![false; PAD_BUTTONS.len()];
+                for &button in PAD_BUTTONS.iter() {
+                    buttons[button as usize] = controller.button(button);
+                }
+                (axes, buttons, Vec::new())
+            }
+            OpenPad::Joystick(ref joystick) => (
+                (0..joystick.num_axes()).map(|i| joystick.axis(i).unwrap_or(0)).collect(),
+                (0..joystick.num_buttons()).map(|i| joystick.button(i).unwrap_or(false)).collect(),
+                (0..joystick.num_hats()).map(|i| joystick.hat(i).map(|hat| hat.to_raw()).unwrap_or(0)).collect(),
+            ),
+        };
+        PadState {
+            instance_id: self.instance_id(),
+            name: self.name(),
+            guid: guid.to_string(),
+            mapped: self.is_mapped(),
+            axes: axes,
+            buttons: buttons,
+            hats: hats,
+        }
+    }
+}
+
+/// Opens pads as they're plugged in and copies what they're doing into
+/// `SdlPads`. SDL has to be used from the thread that set it up, so this
+/// lives wherever the SDL events are handled.
+pub struct PadBridge {
+    controllers: GameControllerSubsystem,
+    joysticks: JoystickSubsystem,
+    open: Vec<(OpenPad, String)>,
+    pads: Arc<Mutex<SdlPads>>,
+}
+
+impl PadBridge {
+    pub fn new(sdl: &Sdl, pads: Arc<Mutex<SdlPads>>) -> Result<PadBridge> {
+        let mut bridge = PadBridge {
+            controllers: sdl.game_controller()?,
+            joysticks: sdl.joystick()?,
+            open: Vec::new(),
+            pads: pads,
+        };
+        bridge.open_all();
+        Ok(bridge)
+    }
+
+    /// Picks up pads being plugged in and unplugged.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            // Controllers show up as joysticks too, so they get both events.
+            Event::JoyDeviceAdded { which, .. } | Event::ControllerDeviceAdded { which, .. } =>
+                self.open_index(which),
+            Event::JoyDeviceRemoved { which, .. } | Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(pos) = self.open.iter().position(|&(ref pad, _)| pad.instance_id() == which) {
+                    let (pad, _) = self.open.remove(pos);
+                    info!("Controller {} unplugged", pad.name());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds any mappings readers asked for and copies every pad's state over.
+    /// Call this once per frame.
+    pub fn update(&mut self) {
+        let mappings: Vec<String> = {
+            let mut pads = self.pads.lock().unwrap_or_else(|e| e.into_inner());
+            pads.take_mappings()
+        };
+        if !mappings.is_empty() {
+            for mapping in mappings.iter() {
+                if let Err(e) = self.controllers.add_mapping(mapping) {
+                    warn!("Could not add controller mapping: {}", e);
+                }
+            }
+            // Joysticks that weren't controllers before might be now.
+            self.open_all();
+        }
+
+        let states = self.open.iter()
+            .map(|&(ref pad, ref guid)| pad.state(guid))
+            .collect();
+        self.pads.lock().unwrap_or_else(|e| e.into_inner()).pads = states;
+    }
+
+    fn open_all(&mut self) {
+        let count = self.controllers.num_joysticks().unwrap_or(0);
+        for index in 0..count {
+            self.open_index(index);
+        }
+    }
+
+    fn open_index(&mut self, index: u32) {
+        let mapped = self.controllers.is_game_controller(index);
+        let pad = if mapped {
+            self.controllers.open(index).map(OpenPad::Controller)
+        }
+        else {
+            self.joysticks.open(index).map(OpenPad::Joystick)
+        };
+        let pad = match pad {
+            Ok(pad) => pad,
+            Err(e) => {
+                warn!("Could not open controller {}: {}", index, e);
+                return;
+            }
+        };
+
+        // SDL hands back the same pad if it's already open. A raw joystick
+        // that has a mapping now gets swapped for the controller, keeping its
+        // place so indexes don't change.
+        let existing = self.open.iter().position(|&(ref p, _)| p.instance_id() == pad.instance_id());
+        if let Some(pos) = existing {
+            if self.open[pos].0.is_mapped() || !mapped {
+                return;
+            }
+        }
+
+        let guid = self.joysticks.device_guid(index)
+            .map(|guid| guid.string())
+            .unwrap_or_default();
+        if mapped {
+            info!("Controller {} plugged in ({})", pad.name(), guid);
+        }
+        else {
+            warn!("Controller {} plugged in ({}), but SDL doesn't know its layout. Reading it as a raw joystick; \
+                   give a `mapping` for it, or map its buttons and axes by number.", pad.name(), guid);
+        }
+        match existing {
+            Some(pos) => self.open[pos] = (pad, guid),
+            None => self.open.push((pad, guid)),
+        }
+    }
+}
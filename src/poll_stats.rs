@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use gc_input::metadata::Metadata;
+use gc_input::stats::secs;
+
+// Number of inputs the rolling rate and jitter are worked out over.
+const RECENT_INTERVALS: usize = 240;
+// Gaps between inputs at least this long get logged.
+const GAP_WARNING: Duration = Duration::from_millis(100);
+
+/// Measures how often an input reader hands back new input.
+#[derive(Clone, Debug)]
+pub struct PollStats {
+    last: Option<Instant>,
+    intervals: VecDeque<Duration>,
+    max_gap: Duration,
+}
+
+impl PollStats {
+    pub fn new() -> PollStats {
+        PollStats {
+            last: None,
+            intervals: VecDeque::with_capacity(RECENT_INTERVALS),
+            max_gap: Duration::from_secs(0),
+        }
+    }
+
+    /// Records an input arriving at `now`.
+    pub fn record(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let interval = now.duration_since(last);
+            if self.intervals.len() == RECENT_INTERVALS {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval);
+            if interval >= GAP_WARNING {
+                warn!("No input for {:.1} ms", secs(interval) * 1000.0);
+            }
+            if interval > self.max_gap {
+                self.max_gap = interval;
+            }
+        }
+        self.last = Some(now);
+    }
+
+    /// Forgets when the last input arrived, so time spent disconnected isn't
+    /// counted as a gap.
+    pub fn pause(&mut self) {
+        self.last = None;
+    }
+
+    fn mean_secs(&self) -> Option<f64> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let total: f64 = self.intervals.iter().map(|&i| secs(i)).sum();
+        Some(total / self.intervals.len() as f64)
+    }
+
+    /// Rolling number of inputs per second.
+    pub fn hz(&self) -> f64 {
+        match self.mean_secs() {
+            Some(mean) if mean > 0.0 => 1.0 / mean,
+            _ => 0.0,
+        }
+    }
+
+    /// Standard deviation of the recent intervals, in milliseconds.
+    pub fn jitter_ms(&self) -> f64 {
+        let mean = match self.mean_secs() {
+            Some(mean) => mean,
+            None => return 0.0,
+        };
+        let variance: f64 = self.intervals.iter()
+            .map(|&i| (secs(i) - mean).powi(2))
+            .sum::<f64>() / self.intervals.len() as f64;
+        variance.sqrt() * 1000.0
+    }
+
+    /// Largest interval this session, in milliseconds.
+    pub fn max_gap_ms(&self) -> f64 {
+        secs(self.max_gap) * 1000.0
+    }
+
+    /// Largest of the recent intervals, in milliseconds.
+    pub fn recent_max_gap_ms(&self) -> f64 {
+        self.intervals.iter()
+            .max()
+            .map(|&i| secs(i) * 1000.0)
+            .unwrap_or(0.0)
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{:.1} Hz, jitter {:.2} ms, max gap {:.1} ms (recent {:.1} ms)",
+            self.hz(),
+            self.jitter_ms(),
+            self.max_gap_ms(),
+            self.recent_max_gap_ms())
+    }
+
+    pub fn add_metadata(&self, metadata: &mut Metadata) {
+        metadata.insert("poll.hz".to_string(), self.hz().into());
+        metadata.insert("poll.jitter_ms".to_string(), self.jitter_ms().into());
+        metadata.insert("poll.max_gap_ms".to_string(), self.max_gap_ms().into());
+        metadata.insert("poll.recent_max_gap_ms".to_string(), self.recent_max_gap_ms().into());
+    }
+}
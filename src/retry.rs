@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use gc_input::configuration::InputSource;
+
+use crate::viewer_configuration::RetryConf;
+
+/// How long to wait between attempts after an input method fails, and when
+/// to give up on it and open it again from scratch.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Number of failures in a row before the input is reopened.
+    pub reopen_after: u32,
+}
+
+impl RetryPolicy {
+    /// Defaults that suit each input method. The serial reader already
+    /// reconnects on its own, so it retries quickly before reopening. The
+    /// others can only recover by being reopened.
+    pub fn for_source(source: &InputSource) -> RetryPolicy {
+        match *source {
+            InputSource::Serial(_) => RetryPolicy {
+                initial_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(2),
+                reopen_after: 3,
+            },
+            InputSource::Dtm(_)
+            | InputSource::Sa2(_)
+            | InputSource::Replay(_)
+            | InputSource::Keyboard(_)
+            | InputSource::Sdl(_)
+            | InputSource::DolphinPipe(_) => RetryPolicy {
+                initial_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(10),
+                reopen_after: 1,
+            },
+        }
+    }
+
+    /// Overrides the defaults with anything set in `conf`.
+    pub fn with_conf(self, conf: &RetryConf) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: conf.initial_ms.map(Duration::from_millis).unwrap_or(self.initial_delay),
+            max_delay: conf.max_ms.map(Duration::from_millis).unwrap_or(self.max_delay),
+            reopen_after: conf.reopen_after.unwrap_or(self.reopen_after).max(1),
+        }
+    }
+}
+
+/// Keeps track of failures in a row under a `RetryPolicy`.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: RetryPolicy) -> Backoff {
+        Backoff {
+            policy: policy,
+            failures: 0,
+        }
+    }
+
+    /// Records a failure and returns how long to wait before trying again.
+    /// The wait doubles with each failure in a row, up to the maximum.
+    pub fn failed(&mut self) -> Duration {
+        let doublings = self.failures.min(16);
+        self.failures = self.failures.saturating_add(1);
+        // A huge `initial_ms` would overflow instead of hitting the maximum.
+        self.policy.initial_delay.checked_mul(1 << doublings)
+            .unwrap_or(self.policy.max_delay)
+            .min(self.policy.max_delay)
+    }
+
+    /// Whether enough failures have happened in a row that the input should
+    /// be reopened.
+    pub fn should_reopen(&self) -> bool {
+        self.failures > 0 && self.failures % self.policy.reopen_after == 0
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(initial_ms: u64, max_ms: u64, reopen_after: u32) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(initial_ms),
+            max_delay: Duration::from_millis(max_ms),
+            reopen_after: reopen_after,
+        }
+    }
+
+    #[test]
+    fn doubles_the_wait_with_each_failure() {
+        let mut backoff = Backoff::new(policy(100, 10_000, 1));
+        let waits: Vec<u64> = (0..4).map(|_| backoff.failed().as_millis() as u64).collect();
+        assert_eq!(waits, vec![100, 200, 400, 800]);
+        assert_eq!(backoff.failures(), 4);
+    }
+
+    #[test]
+    fn caps_the_wait_at_the_maximum() {
+        let mut backoff = Backoff::new(policy(100, 300, 1));
+        let waits: Vec<u64> = (0..4).map(|_| backoff.failed().as_millis() as u64).collect();
+        assert_eq!(waits, vec![100, 200, 300, 300]);
+        // Long after the doubling would have overflowed.
+        for _ in 0..100 {
+            backoff.failed();
+        }
+        assert_eq!(backoff.failed(), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn huge_initial_delay_waits_the_maximum() {
+        let conf = RetryConf {
+            initial_ms: Some(u64::MAX),
+            max_ms: Some(5_000),
+            reopen_after: None,
+        };
+        let mut backoff = Backoff::new(policy(100, 1_000, 1).with_conf(&conf));
+        assert_eq!(backoff.failed(), Duration::from_secs(5));
+        assert_eq!(backoff.failed(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn starts_over_after_succeeding() {
+        let mut backoff = Backoff::new(policy(100, 10_000, 1));
+        backoff.failed();
+        backoff.failed();
+        backoff.succeeded();
+        assert_eq!(backoff.failures(), 0);
+        assert_eq!(backoff.failed(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn reopens_every_so_many_failures() {
+        let mut backoff = Backoff::new(policy(100, 10_000, 3));
+        assert!(!backoff.should_reopen());
+        let reopens: Vec<bool> = (0..6)
+            .map(|_| {
+                backoff.failed();
+                backoff.should_reopen()
+            })
+            .collect();
+        assert_eq!(reopens, vec![false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn treats_reopen_after_0_as_1() {
+        let conf = RetryConf {
+            initial_ms: None,
+            max_ms: None,
+            reopen_after: Some(0),
+        };
+        let mut backoff = Backoff::new(policy(100, 10_000, 3).with_conf(&conf));
+        backoff.failed();
+        assert!(backoff.should_reopen());
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+use gc_input::controller_state::ControllerState;
+use gc_input::metadata::Metadata;
+
+/// Everything the input thread hands to the display thread.
+#[derive(Clone, Debug, Default)]
+pub struct SharedState {
+    pub controller: ControllerState,
+    pub metadata: Metadata,
+    pub disconnected: bool,
+    /// The last error from the input, shown until it starts working again.
+    pub status: Option<String>,
+    pub analog_trail: Trail,
+    pub c_trail: Trail,
+}
+
+/// The last few positions a stick was read at, oldest first. Positions are
+/// recorded as input comes in, so the trail doesn't depend on how often the
+/// window is drawn.
+#[derive(Clone, Debug, Default)]
+pub struct Trail {
+    len: usize,
+    pub positions: VecDeque<(u8, u8)>,
+}
+
+impl Trail {
+    pub fn new(len: usize) -> Trail {
+        Trail {
+            len: len,
+            positions: VecDeque::with_capacity(len),
+        }
+    }
+
+    pub fn record(&mut self, position: (u8, u8)) {
+        if self.len == 0 {
+            return;
+        }
+        if self.positions.len() == self.len {
+            self.positions.pop_front();
+        }
+        self.positions.push_back(position);
+    }
+}
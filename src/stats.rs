@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_derive::Serialize;
+
+use crate::controller_state::{Button, ControllerState, StickPosition};
+use crate::error::Result;
+use crate::metadata::Metadata;
+
+// Window that the current presses per second are counted over.
+const MASH_WINDOW: Duration = Duration::from_secs(1);
+// How far a trigger has to be pressed before it counts as activated.
+const TRIGGER_THRESHOLD: u8 = 43;
+// A flick is the stick going from near the center to the rim within
+// FLICK_TIME.
+const FLICK_CENTER: f32 = 0.3;
+const FLICK_RIM: f32 = 0.95;
+const FLICK_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Default)]
+struct ButtonStats {
+    presses: u64,
+    total_hold: Duration,
+    longest_hold: Duration,
+    pressed_at: Option<Instant>,
+}
+
+impl ButtonStats {
+    /// Total hold time, counting the current hold up to `now`.
+    fn total_hold(&self, now: Instant) -> Duration {
+        self.total_hold + self.current_hold(now)
+    }
+
+    /// Longest hold time, counting the current hold up to `now`.
+    fn longest_hold(&self, now: Instant) -> Duration {
+        self.longest_hold.max(self.current_hold(now))
+    }
+
+    fn current_hold(&self, now: Instant) -> Duration {
+        self.pressed_at
+            .map(|pressed_at| now.duration_since(pressed_at))
+            .unwrap_or(Duration::from_secs(0))
+    }
+}
+
+/// Counts trigger presses, split by whether the trigger was clicked in.
+#[derive(Clone, Debug, Default)]
+struct TriggerStats {
+    analog: u64,
+    digital: u64,
+    active: bool,
+    clicked: bool,
+}
+
+impl TriggerStats {
+    fn update(&mut self, analog: u8, digital: bool) {
+        if analog >= TRIGGER_THRESHOLD || digital {
+            self.active = true;
+            self.clicked |= digital;
+        }
+        else if self.active {
+            if self.clicked {
+                self.digital += 1;
+            }
+            else {
+                self.analog += 1;
+            }
+            self.active = false;
+            self.clicked = false;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct StickStats {
+    flicks: u64,
+    centered_at: Option<Instant>,
+    at_rim: bool,
+}
+
+impl StickStats {
+    fn update(&mut self, raw: (u8, u8), now: Instant) {
+        let magnitude = StickPosition::from_raw(raw).magnitude();
+        if magnitude <= FLICK_CENTER {
+            self.centered_at = Some(now);
+        }
+
+        let at_rim = magnitude >= FLICK_RIM;
+        if at_rim && !self.at_rim {
+            let quick = self.centered_at
+                .map(|centered_at| now.duration_since(centered_at) <= FLICK_TIME)
+                .unwrap_or(false);
+            if quick {
+                self.flicks += 1;
+            }
+        }
+        self.at_rim = at_rim;
+    }
+}
+
+/// Running statistics for the current session.
+#[derive(Clone, Debug)]
+pub struct SessionStats {
+    buttons: HashMap<Button, ButtonStats>,
+    recent_presses: VecDeque<Instant>,
+    peak_presses_per_sec: u64,
+    l_trigger: TriggerStats,
+    r_trigger: TriggerStats,
+    analog: StickStats,
+    c: StickStats,
+    previous: ControllerState,
+    last_update: Instant,
+}
+
+impl SessionStats {
+    pub fn new() -> SessionStats {
+        SessionStats {
+            buttons: HashMap::new(),
+            recent_presses: VecDeque::new(),
+            peak_presses_per_sec: 0,
+            l_trigger: TriggerStats::default(),
+            r_trigger: TriggerStats::default(),
+            analog: StickStats::default(),
+            c: StickStats::default(),
+            previous: ControllerState::default(),
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Takes in the controller state read at `now`.
+    pub fn update(&mut self, state: &ControllerState, now: Instant) {
+        for &button in Button::ALL.iter() {
+            let pressed = state.button(button);
+            let was_pressed = self.previous.button(button);
+            if pressed && !was_pressed {
+                let stats = self.buttons.entry(button).or_insert_with(ButtonStats::default);
+                stats.presses += 1;
+                stats.pressed_at = Some(now);
+                self.recent_presses.push_back(now);
+            }
+            else if !pressed && was_pressed {
+                if let Some(stats) = self.buttons.get_mut(&button) {
+                    let hold = stats.current_hold(now);
+                    stats.total_hold += hold;
+                    stats.longest_hold = stats.longest_hold.max(hold);
+                    stats.pressed_at = None;
+                }
+            }
+        }
+
+        while self.recent_presses.front().map(|&t| now.duration_since(t) > MASH_WINDOW).unwrap_or(false) {
+            self.recent_presses.pop_front();
+        }
+        self.peak_presses_per_sec = self.peak_presses_per_sec.max(self.presses_per_sec());
+
+        self.l_trigger.update(state.l_analog, state.l_digital);
+        self.r_trigger.update(state.r_analog, state.r_digital);
+        self.analog.update(state.analog, now);
+        self.c.update(state.c, now);
+
+        self.previous = *state;
+        self.last_update = now;
+    }
+
+    /// Number of presses of any button over the last second.
+    fn presses_per_sec(&self) -> u64 {
+        self.recent_presses.len() as u64
+    }
+
+    fn button_stats(&self, button: Button) -> ButtonStats {
+        self.buttons.get(&button).cloned().unwrap_or_default()
+    }
+
+    pub fn add_metadata(&self, metadata: &mut Metadata) {
+        let now = self.last_update;
+        for &button in Button::ALL.iter() {
+            let stats = self.button_stats(button);
+            metadata.insert(format!("presses.{}", button.name()), stats.presses.into());
+            metadata.insert(format!("hold.{}", button.name()), secs(stats.total_hold(now)).into());
+            metadata.insert(format!("longest_hold.{}", button.name()), secs(stats.longest_hold(now)).into());
+        }
+        metadata.insert("mash.current".to_string(), self.presses_per_sec().into());
+        metadata.insert("mash.peak".to_string(), self.peak_presses_per_sec.into());
+        metadata.insert("trigger.l.analog".to_string(), self.l_trigger.analog.into());
+        metadata.insert("trigger.l.digital".to_string(), self.l_trigger.digital.into());
+        metadata.insert("trigger.r.analog".to_string(), self.r_trigger.analog.into());
+        metadata.insert("trigger.r.digital".to_string(), self.r_trigger.digital.into());
+        metadata.insert("flicks.analog".to_string(), self.analog.flicks.into());
+        metadata.insert("flicks.c".to_string(), self.c.flicks.into());
+    }
+
+    pub fn report(&self) -> StatsReport {
+        let now = self.last_update;
+        let buttons = Button::ALL.iter()
+            .map(|&button| {
+                let stats = self.button_stats(button);
+                let report = ButtonReport {
+                    presses: stats.presses,
+                    total_hold_secs: secs(stats.total_hold(now)),
+                    longest_hold_secs: secs(stats.longest_hold(now)),
+                };
+                (button.name().to_string(), report)
+            })
+            .collect();
+
+        StatsReport {
+            buttons: buttons,
+            peak_presses_per_sec: self.peak_presses_per_sec,
+            l_trigger: TriggerReport {
+                analog: self.l_trigger.analog,
+                digital: self.l_trigger.digital,
+            },
+            r_trigger: TriggerReport {
+                analog: self.r_trigger.analog,
+                digital: self.r_trigger.digital,
+            },
+            analog_flicks: self.analog.flicks,
+            c_flicks: self.c.flicks,
+        }
+    }
+
+    /// Writes the session's statistics to `path` as JSON.
+    pub fn save<P>(&self, path: P) -> Result<()>
+        where P: AsRef<Path>,
+    {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &self.report())?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ButtonReport {
+    pub presses: u64,
+    pub total_hold_secs: f64,
+    pub longest_hold_secs: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TriggerReport {
+    pub analog: u64,
+    pub digital: u64,
+}
+
+/// Everything `SessionStats` has counted, in a form that can be saved.
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsReport {
+    pub buttons: BTreeMap<String, ButtonReport>,
+    pub peak_presses_per_sec: u64,
+    pub l_trigger: TriggerReport,
+    pub r_trigger: TriggerReport,
+    pub analog_flicks: u64,
+    pub c_flicks: u64,
+}
+
+/// Converts `duration` to seconds.
+pub fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
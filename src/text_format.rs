@@ -0,0 +1,133 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use gc_input::error::{Error, Result};
+use gc_input::metadata::Value;
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        width: Option<usize>,
+        precision: Option<usize>,
+    },
+}
+
+/// A text template such as `"{analog.x:3} {analog.y:3}"`.
+///
+/// Fields are written as `{name}`, `{name:width}` or `{name:width.precision}`.
+/// Use `{{` and `}}` for literal braces.
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(format: &str) -> Result<Template> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(literal.clone()));
+                        literal.clear();
+                    }
+                    segments.push(Self::parse_field(&mut chars, format)?);
+                }
+                '}' => return Err(Error::FormatError(format!("unmatched '}}' in \"{}\"", format))),
+                _ => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template {
+            segments: segments,
+        })
+    }
+
+    fn parse_field(chars: &mut Peekable<Chars>, format: &str) -> Result<Segment> {
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(ch) => field.push(ch),
+                None => return Err(Error::FormatError(format!("unclosed '{{' in \"{}\"", format))),
+            }
+        }
+
+        let mut parts = field.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        if name.is_empty() {
+            return Err(Error::FormatError(format!("empty field name in \"{}\"", format)));
+        }
+
+        let (width, precision) = match parts.next() {
+            Some(spec) => {
+                let mut spec_parts = spec.splitn(2, '.');
+                let width = Self::parse_number(spec_parts.next(), format)?;
+                let precision = Self::parse_number(spec_parts.next(), format)?;
+                (width, precision)
+            }
+            None => (None, None),
+        };
+
+        Ok(Segment::Field {
+            name: name,
+            width: width,
+            precision: precision,
+        })
+    }
+
+    fn parse_number(part: Option<&str>, format: &str) -> Result<Option<usize>> {
+        match part {
+            Some(part) if !part.is_empty() => {
+                part.parse()
+                    .map(Some)
+                    .map_err(|_| Error::FormatError(format!("bad format spec \"{}\" in \"{}\"", part, format)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Fills in the template. Fields that `lookup` doesn't know about are
+    /// shown as `?`.
+    pub fn render<F>(&self, mut lookup: F) -> String
+        where F: FnMut(&str) -> Option<Value>,
+    {
+        let mut out = String::new();
+
+        for segment in self.segments.iter() {
+            match *segment {
+                Segment::Literal(ref literal) => out.push_str(literal),
+                Segment::Field { ref name, width, precision } => {
+                    let width = width.unwrap_or(0);
+                    let text = match lookup(name) {
+                        Some(Value::Int(value)) => format!("{:>width$}", value, width = width),
+                        Some(Value::Float(value)) => {
+                            format!("{:>width$.precision$}", value, width = width, precision = precision.unwrap_or(2))
+                        }
+                        Some(Value::Text(value)) => format!("{:<width$}", value, width = width),
+                        None => format!("{:>width$}", "?", width = width),
+                    };
+                    out.push_str(&text);
+                }
+            }
+        }
+
+        out
+    }
+}
@@ -1,12 +1,18 @@
 use std::path::{Path, PathBuf};
 
-use sdl2::render::{Canvas, TextureCreator, Texture};
+use log::debug;
+use sdl2::render::{BlendMode, Canvas, TextureCreator, Texture};
 use sdl2::image::LoadTexture;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
+use sdl2::ttf::{self, Font, Sdl2TtfContext};
 use sdl2::video::{Window, WindowContext};
 
-use crate::configuration::{ThemeConfiguration, ImageConf, AnalogConf, TriggerConf, TriggerDirection};
-use crate::error::Result;
+use gc_input::error::{Error, Result};
+
+use crate::heatmap::HEATMAP_SIZE;
+use crate::text_format::Template;
+use crate::theme::{ThemeConfiguration, ImageConf, AnalogConf, TriggerConf, TriggerDirection, StickConf, HeatmapConf, TextConf};
 
 pub struct Image<'a> {
     pub tex: Texture<'a>,
@@ -24,6 +30,36 @@ pub struct Trigger<'a> {
     pub direction: TriggerDirection,
 }
 
+pub struct Text<'a> {
+    pub template: Template,
+    pub dst: (i32, i32),
+    pub font: Option<Font<'a, 'static>>,
+    pub scale: u32,
+    pub color: Color,
+    /// The text last drawn with `font` and a texture for each of its lines,
+    /// so it's only rendered again when it changes.
+    pub rendered: Option<(String, Vec<(Texture<'a>, Rect)>)>,
+}
+
+// Notch angles of a standard GameCube octagonal gate, in degrees.
+const DEFAULT_NOTCHES: [f32; 8] = [0.0, 45.0, 90.0, 135.0, 180.0, 225.0, 270.0, 315.0];
+
+pub struct Stick {
+    pub center: (i32, i32),
+    pub radius: u32,
+    pub deadzone: f32,
+    pub notches: Vec<f32>,
+    pub readout: Option<(i32, i32)>,
+    pub readout_scale: u32,
+    pub color: Color,
+}
+
+pub struct HeatmapImage<'a> {
+    pub tex: Texture<'a>,
+    pub dst: Rect,
+    pub color: Color,
+}
+
 pub struct TextureCache<'a> {
     pub background: Image<'a>,
     pub a: Option<Image<'a>>,
@@ -42,17 +78,32 @@ pub struct TextureCache<'a> {
     pub l_digital: Option<Image<'a>>,
     pub r_digital: Option<Image<'a>>,
     pub z: Option<Image<'a>>,
+    pub select: Option<Image<'a>>,
+    pub home: Option<Image<'a>>,
+    pub zl: Option<Image<'a>>,
+    pub zr: Option<Image<'a>>,
+    pub c_up: Option<Image<'a>>,
+    pub c_down: Option<Image<'a>>,
+    pub c_left: Option<Image<'a>>,
+    pub c_right: Option<Image<'a>>,
+    pub analog_stick: Option<Stick>,
+    pub c_stick: Option<Stick>,
+    pub analog_heatmap: Option<HeatmapImage<'a>>,
+    pub c_heatmap: Option<HeatmapImage<'a>>,
+    pub text: Vec<Text<'a>>,
 }
 
 pub struct TextureCacheCreator<T> {
     path: PathBuf,
     tex_creator: TextureCreator<T>,
+    ttf: Sdl2TtfContext,
 }
 
 impl<T> TextureCacheCreator<T> {
     fn read_image<'a>(&'a self, conf: &ImageConf) -> Result<Image<'a>> {
         let final_path = self.path.join(&conf.path);
-        let tex = self.tex_creator.load_texture(final_path)?;
+        let tex = self.tex_creator.load_texture(&final_path)?;
+        debug!("Loaded image {}", final_path.display());
         let (w, h) = conf.size.unwrap_or_else(|| {
             let query = tex.query();
             (query.width, query.height)
@@ -85,6 +136,81 @@ impl<T> TextureCacheCreator<T> {
         })
     }
 
+    fn read_stick(&self, conf: &StickConf) -> Stick {
+        let color = conf.color.unwrap_or((0, 0, 0));
+
+        Stick {
+            center: conf.center,
+            radius: conf.radius,
+            deadzone: conf.deadzone.unwrap_or(0.0),
+            notches: conf.notches.clone().unwrap_or_else(|| DEFAULT_NOTCHES.to_vec()),
+            readout: conf.readout,
+            readout_scale: conf.readout_scale.unwrap_or(2),
+            color: Color::RGB(color.0, color.1, color.2),
+        }
+    }
+
+    fn read_heatmap<'a>(&'a self, conf: &HeatmapConf) -> Result<HeatmapImage<'a>> {
+        let mut tex = self.tex_creator.create_texture_streaming(PixelFormatEnum::RGBA8888, HEATMAP_SIZE, HEATMAP_SIZE)
+            .map_err(|e| Error::Sdl2Error(e.into()))?;
+        tex.set_blend_mode(BlendMode::Blend);
+        let size = conf.size.unwrap_or(HEATMAP_SIZE / 2);
+        let color = conf.color.unwrap_or((255, 0, 0));
+
+        Ok(HeatmapImage {
+            tex: tex,
+            dst: Rect::new(conf.dst.0, conf.dst.1, size, size),
+            color: Color::RGB(color.0, color.1, color.2),
+        })
+    }
+
+    fn read_text<'a>(&'a self, conf: &TextConf) -> Result<Text<'a>> {
+        let template = Template::parse(&conf.format)?;
+        let font = match conf.font.as_ref() {
+            Some(font) => Some(self.ttf.load_font(self.path.join(font), conf.font_size.unwrap_or(16))?),
+            None => None,
+        };
+        let color = conf.color.unwrap_or((0, 0, 0));
+
+        Ok(Text {
+            template: template,
+            dst: conf.dst,
+            font: font,
+            scale: conf.scale.unwrap_or(2),
+            color: Color::RGB(color.0, color.1, color.2),
+            rendered: None,
+        })
+    }
+
+    /// Renders each line of `rendered` in the text element's font, unless
+    /// that's what it already shows.
+    pub fn render_text<'a>(&'a self, text: &mut Text<'a>, rendered: String) -> Result<()> {
+        if text.rendered.as_ref().map(|&(ref last, _)| last) == Some(&rendered) {
+            return Ok(());
+        }
+        let font = match text.font {
+            Some(ref font) => font,
+            None => return Ok(()),
+        };
+
+        let mut lines = Vec::new();
+        let mut y = text.dst.1;
+        for line in rendered.lines() {
+            // SDL_ttf refuses to render empty strings.
+            if !line.is_empty() {
+                let surface = font.render(line)
+                    .blended(text.color)
+                    .map_err(|e| Error::Sdl2Error(e.into()))?;
+                let tex = self.tex_creator.create_texture_from_surface(&surface)
+                    .map_err(|e| Error::Sdl2Error(e.into()))?;
+                lines.push((tex, Rect::new(text.dst.0, y, surface.width(), surface.height())));
+            }
+            y += font.recommended_line_spacing();
+        }
+        text.rendered = Some((rendered, lines));
+        Ok(())
+    }
+
     pub fn texture_cache(&self, conf: &ThemeConfiguration) -> Result<TextureCache> {
         let background = self.read_image(&conf.background)?;
         let a = match conf.a.as_ref() {
@@ -151,6 +277,52 @@ impl<T> TextureCacheCreator<T> {
             Some(image) => Some(self.read_image(image)?),
             None => None,
         };
+        let select = match conf.select.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let home = match conf.home.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let zl = match conf.zl.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let zr = match conf.zr.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let c_up = match conf.c_up.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let c_down = match conf.c_down.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let c_left = match conf.c_left.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let c_right = match conf.c_right.as_ref() {
+            Some(image) => Some(self.read_image(image)?),
+            None => None,
+        };
+        let analog_stick = conf.analog_stick.as_ref().map(|stick| self.read_stick(stick));
+        let c_stick = conf.c_stick.as_ref().map(|stick| self.read_stick(stick));
+        let analog_heatmap = match conf.analog_heatmap.as_ref() {
+            Some(heatmap) => Some(self.read_heatmap(heatmap)?),
+            None => None,
+        };
+        let c_heatmap = match conf.c_heatmap.as_ref() {
+            Some(heatmap) => Some(self.read_heatmap(heatmap)?),
+            None => None,
+        };
+        let mut text = Vec::new();
+        for text_conf in conf.text.iter().flatten() {
+            text.push(self.read_text(text_conf)?);
+        }
 
         Ok(TextureCache {
             background: background,
@@ -170,25 +342,40 @@ impl<T> TextureCacheCreator<T> {
             l_digital: l_digital,
             r_digital: r_digital,
             z: z,
+            select: select,
+            home: home,
+            zl: zl,
+            zr: zr,
+            c_up: c_up,
+            c_down: c_down,
+            c_left: c_left,
+            c_right: c_right,
+            analog_stick: analog_stick,
+            c_stick: c_stick,
+            analog_heatmap: analog_heatmap,
+            c_heatmap: c_heatmap,
+            text: text,
         })
     }
 }
 
 pub trait CanvasExt<T> {
-    fn texture_cache_creator<P>(&self, path: P) -> TextureCacheCreator<T>
+    fn texture_cache_creator<P>(&self, path: P) -> Result<TextureCacheCreator<T>>
         where P: AsRef<Path>;
 }
 
 impl CanvasExt<WindowContext> for Canvas<Window> {
-    fn texture_cache_creator<P>(&self, path: P) -> TextureCacheCreator<WindowContext>
+    fn texture_cache_creator<P>(&self, path: P) -> Result<TextureCacheCreator<WindowContext>>
         where P: AsRef<Path>,
     {
         let tex_creator = self.texture_creator();
+        let ttf = ttf::init().map_err(|e| Error::Sdl2Error(e.into()))?;
 
-        TextureCacheCreator {
+        Ok(TextureCacheCreator {
             // TODO: I want Path to clone and PathBuf to move.
             path: path.as_ref().to_owned(),
             tex_creator: tex_creator,
-        }
+            ttf: ttf,
+        })
     }
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use gc_input::error::{Error, Result};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImageConf {
+    pub path: String,
+    pub dst: (i32, i32),
+    pub size: Option<(u32, u32)>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnalogConf {
+    pub image: ImageConf,
+    pub range: (i32, i32),
+    pub line_from: Option<(i32, i32)>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TriggerDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TriggerConf {
+    pub image: ImageConf,
+    pub direction: TriggerDirection,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StickConf {
+    pub center: (i32, i32),
+    pub radius: u32,
+    pub deadzone: Option<f32>,
+    pub notches: Option<Vec<f32>>,
+    pub trail: Option<usize>,
+    pub readout: Option<(i32, i32)>,
+    pub readout_scale: Option<u32>,
+    pub color: Option<(u8, u8, u8)>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeatmapConf {
+    pub dst: (i32, i32),
+    pub size: Option<u32>,
+    pub color: Option<(u8, u8, u8)>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TextConf {
+    pub format: String,
+    pub dst: (i32, i32),
+    pub font: Option<String>,
+    pub font_size: Option<u16>,
+    pub scale: Option<u32>,
+    pub color: Option<(u8, u8, u8)>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThemeConfiguration {
+    pub size: (u32, u32),
+    pub background: ImageConf,
+    pub a: Option<ImageConf>,
+    pub b: Option<ImageConf>,
+    pub x: Option<ImageConf>,
+    pub y: Option<ImageConf>,
+    pub up: Option<ImageConf>,
+    pub down: Option<ImageConf>,
+    pub left: Option<ImageConf>,
+    pub right: Option<ImageConf>,
+    pub start: Option<ImageConf>,
+    pub analog: Option<AnalogConf>,
+    pub c: Option<AnalogConf>,
+    pub l_analog: Option<TriggerConf>,
+    pub r_analog: Option<TriggerConf>,
+    pub l_digital: Option<ImageConf>,
+    pub r_digital: Option<ImageConf>,
+    pub z: Option<ImageConf>,
+    pub select: Option<ImageConf>,
+    pub home: Option<ImageConf>,
+    pub zl: Option<ImageConf>,
+    pub zr: Option<ImageConf>,
+    pub c_up: Option<ImageConf>,
+    pub c_down: Option<ImageConf>,
+    pub c_left: Option<ImageConf>,
+    pub c_right: Option<ImageConf>,
+    pub analog_stick: Option<StickConf>,
+    pub c_stick: Option<StickConf>,
+    pub analog_heatmap: Option<HeatmapConf>,
+    pub c_heatmap: Option<HeatmapConf>,
+    pub text: Option<Vec<TextConf>>,
+}
+
+impl ThemeConfiguration {
+    pub fn from_read<R>(reader: R) -> Result<ThemeConfiguration>
+        where R: Read,
+    {
+        Ok(serde_yaml::from_reader(reader)?)
+    }
+
+    pub fn from_path<P>(path: P) -> Result<ThemeConfiguration>
+        where P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(Self::from_read)
+            .map_err(|e| Error::ConfigError(path.to_owned(), Box::new(e)))
+    }
+}
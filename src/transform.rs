@@ -0,0 +1,225 @@
+use crate::configuration::{AxisConf, TransformConf};
+use crate::controller_state::{Button, ControllerState};
+use crate::error::{Error, Result};
+
+const STICK_CENTER: f32 = 128.0;
+const TRIGGER_REST: f32 = 0.0;
+// Inverted triggers are flipped end to end instead of around where they rest.
+const TRIGGER_MIDDLE: f32 = 127.5;
+
+/// Adjusts one axis of raw input. The deadzone is checked for each axis on
+/// its own, so on a stick it's a square rather than a circle.
+#[derive(Clone, Copy, Debug)]
+struct Axis {
+    center: f32,
+    /// What inverting flips the axis around.
+    middle: f32,
+    offset: f32,
+    scale: f32,
+    deadzone: f32,
+    invert: bool,
+}
+
+impl Axis {
+    fn from_conf(conf: Option<&AxisConf>, center: f32, middle: f32) -> Axis {
+        let default = AxisConf::default();
+        let conf = conf.unwrap_or(&default);
+        Axis {
+            center: center,
+            middle: middle,
+            offset: conf.offset.unwrap_or(0) as f32,
+            scale: conf.scale.unwrap_or(1.0),
+            deadzone: conf.deadzone.unwrap_or(0) as f32,
+            invert: conf.invert.unwrap_or(false),
+        }
+    }
+
+    fn apply(&self, raw: u8) -> u8 {
+        let mut distance = raw as f32 + self.offset - self.center;
+        if distance.abs() <= self.deadzone {
+            distance = 0.0;
+        }
+        let mut value = self.center + distance * self.scale;
+        if self.invert {
+            value = 2.0 * self.middle - value;
+        }
+        value.round().max(0.0).min(255.0) as u8
+    }
+}
+
+/// Cleans up input between a reader and everything that uses it, so themes
+/// look the same whatever the input came from.
+#[derive(Clone, Debug)]
+pub struct Transform {
+    analog_x: Axis,
+    analog_y: Axis,
+    c_x: Axis,
+    c_y: Axis,
+    l_analog: Axis,
+    r_analog: Axis,
+    remap: Vec<(Button, Button)>,
+}
+
+impl Transform {
+    pub fn from_conf(conf: &TransformConf) -> Result<Transform> {
+        let mut remap = Vec::new();
+        for (from, to) in conf.remap.iter().flatten() {
+            remap.push((parse_button(from)?, parse_button(to)?));
+        }
+
+        Ok(Transform {
+            analog_x: Axis::from_conf(conf.analog_x.as_ref(), STICK_CENTER, STICK_CENTER),
+            analog_y: Axis::from_conf(conf.analog_y.as_ref(), STICK_CENTER, STICK_CENTER),
+            c_x: Axis::from_conf(conf.c_x.as_ref(), STICK_CENTER, STICK_CENTER),
+            c_y: Axis::from_conf(conf.c_y.as_ref(), STICK_CENTER, STICK_CENTER),
+            l_analog: Axis::from_conf(conf.l_analog.as_ref(), TRIGGER_REST, TRIGGER_MIDDLE),
+            r_analog: Axis::from_conf(conf.r_analog.as_ref(), TRIGGER_REST, TRIGGER_MIDDLE),
+            remap: remap,
+        })
+    }
+
+    pub fn apply(&self, state: ControllerState) -> ControllerState {
+        let mut new_state = state;
+        new_state.analog = (self.analog_x.apply(state.analog.0), self.analog_y.apply(state.analog.1));
+        new_state.c = (self.c_x.apply(state.c.0), self.c_y.apply(state.c.1));
+        new_state.l_analog = self.l_analog.apply(state.l_analog);
+        new_state.r_analog = self.r_analog.apply(state.r_analog);
+
+        // A remapped button only shows up as what it was mapped to, so
+        // mapping `a` to `b` and `b` to `a` swaps them.
+        for &(from, _) in self.remap.iter() {
+            new_state.set_button(from, false);
+        }
+        for &(from, to) in self.remap.iter() {
+            let pressed = new_state.button(to) || state.button(from);
+            new_state.set_button(to, pressed);
+        }
+
+        new_state
+    }
+}
+
+fn parse_button(name: &str) -> Result<Button> {
+    Button::from_name(name)
+        .ok_or_else(|| Error::FormatError(format!("unknown button \"{}\" in remap", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn sticks(analog: (u8, u8)) -> ControllerState {
+        ControllerState {
+            analog: analog,
+            ..ControllerState::default()
+        }
+    }
+
+    #[test]
+    fn does_nothing_by_default() {
+        let transform = Transform::from_conf(&TransformConf::default()).unwrap();
+        let state = ControllerState {
+            a: true,
+            analog: (10, 250),
+            c: (0, 255),
+            l_analog: 40,
+            r_analog: 255,
+            ..ControllerState::default()
+        };
+        assert_eq!(transform.apply(state), state);
+    }
+
+    #[test]
+    fn adds_the_offset_first() {
+        let transform = Transform::from_conf(&TransformConf {
+            analog_x: Some(AxisConf { offset: Some(-5), ..AxisConf::default() }),
+            analog_y: Some(AxisConf { offset: Some(200), ..AxisConf::default() }),
+            ..TransformConf::default()
+        }).unwrap();
+        assert_eq!(transform.apply(sticks((133, 128))).analog, (128, 255));
+        assert_eq!(transform.apply(sticks((2, 0))).analog, (0, 200));
+    }
+
+    #[test]
+    fn snaps_each_axis_inside_the_deadzone_to_the_center() {
+        let transform = Transform::from_conf(&TransformConf {
+            analog_x: Some(AxisConf { deadzone: Some(10), ..AxisConf::default() }),
+            analog_y: Some(AxisConf { deadzone: Some(10), ..AxisConf::default() }),
+            ..TransformConf::default()
+        }).unwrap();
+        assert_eq!(transform.apply(sticks((138, 118))).analog, (128, 128));
+        assert_eq!(transform.apply(sticks((139, 117))).analog, (139, 117));
+        // Square, not round: a diagonal past the radius still snaps.
+        assert_eq!(transform.apply(sticks((137, 137))).analog, (128, 128));
+    }
+
+    #[test]
+    fn scales_the_distance_from_the_center() {
+        let transform = Transform::from_conf(&TransformConf {
+            analog_x: Some(AxisConf { scale: Some(1.5), ..AxisConf::default() }),
+            l_analog: Some(AxisConf { scale: Some(2.0), ..AxisConf::default() }),
+            ..TransformConf::default()
+        }).unwrap();
+        assert_eq!(transform.apply(sticks((148, 128))).analog, (158, 128));
+        assert_eq!(transform.apply(sticks((28, 128))).analog, (0, 128));
+        let state = transform.apply(ControllerState {
+            l_analog: 100,
+            ..ControllerState::default()
+        });
+        assert_eq!(state.l_analog, 200);
+    }
+
+    #[test]
+    fn inverts_sticks_around_the_center_and_triggers_end_to_end() {
+        let invert = || Some(AxisConf { invert: Some(true), ..AxisConf::default() });
+        let transform = Transform::from_conf(&TransformConf {
+            analog_x: invert(),
+            analog_y: invert(),
+            l_analog: invert(),
+            ..TransformConf::default()
+        }).unwrap();
+        assert_eq!(transform.apply(sticks((128, 28))).analog, (128, 228));
+        assert_eq!(transform.apply(sticks((0, 255))).analog, (255, 1));
+        let state = transform.apply(ControllerState {
+            l_analog: 0,
+            r_analog: 0,
+            ..ControllerState::default()
+        });
+        assert_eq!((state.l_analog, state.r_analog), (255, 0));
+    }
+
+    #[test]
+    fn remapping_both_ways_swaps_buttons() {
+        let mut remap = BTreeMap::new();
+        remap.insert("a".to_string(), "b".to_string());
+        remap.insert("b".to_string(), "a".to_string());
+        let transform = Transform::from_conf(&TransformConf {
+            remap: Some(remap),
+            ..TransformConf::default()
+        }).unwrap();
+
+        let state = transform.apply(ControllerState {
+            a: true,
+            ..ControllerState::default()
+        });
+        assert!(!state.a && state.b);
+        let state = transform.apply(ControllerState {
+            a: true,
+            b: true,
+            ..ControllerState::default()
+        });
+        assert!(state.a && state.b);
+    }
+
+    #[test]
+    fn rejects_unknown_buttons_in_remap() {
+        let mut remap = BTreeMap::new();
+        remap.insert("a".to_string(), "turbo".to_string());
+        assert!(Transform::from_conf(&TransformConf {
+            remap: Some(remap),
+            ..TransformConf::default()
+        }).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use gc_input::configuration::{InputSource, OutputConf, TransformConf};
+use gc_input::error::{Error, Result};
+
+use crate::theme::ThemeConfiguration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleFilter {
+    Nearest,
+    Linear,
+    Integer,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct WindowConf {
+    pub scale: Option<f32>,
+    pub filter: Option<ScaleFilter>,
+    pub title: Option<String>,
+    pub position: Option<(i32, i32)>,
+    pub borderless: Option<bool>,
+    pub always_on_top: Option<bool>,
+    pub debug_overlay: Option<bool>,
+}
+
+/// Window settings that are saved when the viewer closes and restored the
+/// next time it starts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WindowState {
+    pub position: (i32, i32),
+}
+
+impl WindowState {
+    pub fn from_path<P>(path: P) -> Result<WindowState>
+        where P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        Ok(serde_yaml::from_reader(file)?)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+        where P: AsRef<Path>,
+    {
+        let mut file = File::create(path)?;
+        serde_yaml::to_writer(&mut file, self)?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LogConf {
+    pub level: Option<String>,
+    pub stderr: Option<bool>,
+    pub file: Option<PathBuf>,
+    pub max_kb: Option<u64>,
+    pub keep: Option<u32>,
+}
+
+/// How to retry after an input method fails. Anything left out uses the
+/// input method's defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RetryConf {
+    pub initial_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    pub reopen_after: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Configuration {
+    /// The theme, the file it came from, and the directory its images are
+    /// in. Only loaded when there's a window to show it in.
+    pub theme: Option<ThemeConfiguration>,
+    pub theme_file: Option<PathBuf>,
+    pub theme_path: Option<PathBuf>,
+    pub input: InputSource,
+    pub window: WindowConf,
+    pub poll_log_secs: Option<u64>,
+    pub transform: TransformConf,
+    pub retry: RetryConf,
+    pub log: LogConf,
+    pub outputs: Vec<OutputConf>,
+}
+
+impl Configuration {
+    pub fn from_read<R>(reader: R) -> Result<Configuration>
+        where R: Read,
+    {
+        let conf_file: ConfigurationFile = serde_yaml::from_reader(reader)?;
+        let (theme, theme_file, theme_path) = match conf_file.theme_path {
+            _ if conf_file.headless => (None, None, None),
+            Some(theme_file) => {
+                let theme = ThemeConfiguration::from_path(&theme_file)?;
+                let theme_path = theme_file
+                    .parent()
+                    .unwrap_or(Path::new("/"))
+                    .to_owned();
+                (Some(theme), Some(theme_file), Some(theme_path))
+            }
+            None => return Err(Error::FormatError("theme_path is needed unless headless is set".to_string())),
+        };
+        if let (true, &InputSource::Keyboard(_)) = (conf_file.headless, &conf_file.input) {
+            return Err(Error::FormatError("the keyboard input method needs the window, so it can't be used with headless".to_string()));
+        }
+        // `stats_path` is from before there were outputs.
+        let mut outputs = conf_file.outputs;
+        if let Some(stats_path) = conf_file.stats_path {
+            outputs.push(OutputConf::Stats(stats_path));
+        }
+
+        Ok(Configuration {
+            theme: theme,
+            theme_file: theme_file,
+            theme_path: theme_path,
+            input: conf_file.input,
+            window: conf_file.window,
+            poll_log_secs: conf_file.poll_log_secs,
+            transform: conf_file.transform,
+            retry: conf_file.retry,
+            log: conf_file.log,
+            outputs: outputs,
+        })
+    }
+
+    pub fn from_path<P>(path: P) -> Result<Configuration>
+        where P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(Self::from_read)
+            .map_err(|e| match e {
+                // Errors in the theme already say which file they're from.
+                Error::ConfigError(..) => e,
+                e => Error::ConfigError(path.to_owned(), Box::new(e)),
+            })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConfigurationFile {
+    theme_path: Option<PathBuf>,
+    input: InputSource,
+    #[serde(default)]
+    window: WindowConf,
+    #[serde(default)]
+    headless: bool,
+    poll_log_secs: Option<u64>,
+    stats_path: Option<PathBuf>,
+    #[serde(default)]
+    transform: TransformConf,
+    #[serde(default)]
+    retry: RetryConf,
+    #[serde(default)]
+    log: LogConf,
+    #[serde(default)]
+    outputs: Vec<OutputConf>,
+}